@@ -0,0 +1,142 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use private_box::{
+    decrypt, encrypt, encrypt_into, encrypt_owned, encrypted_len, Decryptor, Keypair, PublicKey,
+    SecretKey, SessionEncryptor,
+};
+
+const RECIPIENTS: [usize; 3] = [1, 3, 7];
+const SIZES: [usize; 3] = [64, 1024, 64 * 1024];
+
+fn keys(n: usize) -> (Vec<Keypair>, Vec<PublicKey>) {
+    let keys: Vec<Keypair> = (0..n).map(|_| Keypair::generate()).collect();
+    let recps = keys.iter().map(|k| k.public).collect();
+    (keys, recps)
+}
+
+fn bench_encrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypt");
+    for &n in &RECIPIENTS {
+        let (_, recps) = keys(n);
+        for &size in &SIZES {
+            let msg = vec![0; size];
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} recipients", n), size),
+                &msg,
+                |b, msg| b.iter(|| encrypt(msg, &recps).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_encrypt_into(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypt_into");
+    let (_, recps) = keys(1);
+    for &size in &SIZES {
+        let msg = vec![0; size];
+        let mut buf = Vec::with_capacity(encrypted_len(size, 1));
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("allocating", size), &msg, |b, msg| {
+            b.iter(|| encrypt(msg, &recps).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("reused buffer", size), &msg, |b, msg| {
+            b.iter(|| encrypt_into(msg, &recps, &mut buf).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_encrypt_owned(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypt_owned");
+    let (_, recps) = keys(1);
+    for &size in &[64 * 1024, 1024 * 1024] {
+        let msg = vec![0; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("borrowed", size), &msg, |b, msg| {
+            b.iter(|| encrypt(msg, &recps).unwrap())
+        });
+        // The plaintext has room for the message, so nothing is allocated.
+        group.bench_with_input(BenchmarkId::new("owned", size), &msg, |b, msg| {
+            b.iter_batched(
+                || {
+                    let mut plaintext = Vec::with_capacity(encrypted_len(size, 1));
+                    plaintext.extend_from_slice(msg);
+                    plaintext
+                },
+                |plaintext| encrypt_owned(plaintext, &recps).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// A session derives the shared secrets once, rather than per message, so
+// the gap should grow with the number of recipients.
+fn bench_session(c: &mut Criterion) {
+    let mut group = c.benchmark_group("session");
+    for &n in &RECIPIENTS {
+        let (_, recps) = keys(n);
+        let msg = vec![0; 64];
+        let mut session = SessionEncryptor::new(&recps).unwrap();
+        group.bench_with_input(BenchmarkId::new("encrypt", n), &msg, |b, msg| {
+            b.iter(|| encrypt(msg, &recps).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("session", n), &msg, |b, msg| {
+            b.iter(|| session.encrypt(msg).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_decrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decrypt");
+    for &n in &RECIPIENTS {
+        let (keys, recps) = keys(n);
+        for &size in &SIZES {
+            let cypher = encrypt(&vec![0; size], &recps).unwrap();
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{} recipients", n), size),
+                &cypher,
+                |b, cypher| b.iter(|| decrypt(cypher, &keys[0]).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+// The body is tiny, so this is mostly the cost of trying key slots.
+fn bench_header_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("header scan");
+    let (keys, recps) = keys(7);
+    let cypher = encrypt(&[0; 64], &recps).unwrap();
+    let eve = Keypair::generate();
+
+    group.bench_function("first slot", |b| {
+        b.iter(|| decrypt(&cypher, &keys[0]).unwrap())
+    });
+    let cached = Decryptor::new(&SecretKey::from(&keys[0]));
+    group.bench_function("first slot, cached key", |b| {
+        b.iter(|| cached.decrypt(&cypher).unwrap())
+    });
+    group.bench_function("last slot", |b| {
+        b.iter(|| decrypt(&cypher, &keys[6]).unwrap())
+    });
+    group.bench_function("not a recipient", |b| {
+        b.iter(|| assert!(decrypt(&cypher, &eve).is_none()))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_encrypt,
+    bench_encrypt_into,
+    bench_encrypt_owned,
+    bench_session,
+    bench_decrypt,
+    bench_header_scan
+);
+criterion_main!(benches);
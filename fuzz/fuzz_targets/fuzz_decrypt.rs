@@ -0,0 +1,36 @@
+//! Feeds arbitrary bytes to everything that parses a message.
+//!
+//! The first 32 bytes of the input are the seed of the keypair to decrypt
+//! with, and the rest is the message. The seed corpus is made of messages
+//! addressed to the keypair made from its seed, so decryption succeeds often
+//! enough for the fuzzer to get past the key slots.
+#![no_main]
+use std::io::Read;
+
+use libfuzzer_sys::fuzz_target;
+use private_box::{decrypt, decrypt_checked, decrypt_into, decrypt_key, stream, Keypair};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 32 {
+        return;
+    }
+    let (seed, cyphertext) = data.split_at(32);
+    let keypair = Keypair::from_seed(seed).unwrap();
+
+    let plaintext = decrypt(cyphertext, &keypair);
+    assert_eq!(decrypt_checked(cyphertext, &keypair).ok(), plaintext);
+    if plaintext.is_some() {
+        assert!(decrypt_key(cyphertext, &keypair).is_some());
+    }
+
+    let mut out = vec![1, 2, 3];
+    let opened = decrypt_into(cyphertext, &keypair, &mut out);
+    match &plaintext {
+        Some(p) => assert!(opened && &out == p),
+        None => assert!(!opened && out == [1, 2, 3]),
+    }
+
+    if let Ok(mut dec) = stream::Decryptor::new(cyphertext, &keypair) {
+        let _ = dec.read_to_end(&mut Vec::new());
+    }
+});
@@ -0,0 +1,56 @@
+# Tests for the `python` feature's bindings. Run by `cargo test --features
+# python`, or with pytest against a module built by maturin.
+import base64
+import json
+import os
+
+import private_box
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+with open(os.path.join(HERE, "..", "test", "simple.json")) as f:
+    SIMPLE = json.load(f)
+
+
+def keys():
+    return [
+        (base64.b64decode(k["public"]), base64.b64decode(k["secret"]))
+        for k in SIMPLE["keys"]
+    ]
+
+
+def raises_value_error(f):
+    try:
+        f()
+    except ValueError:
+        return True
+    return False
+
+
+def test_decrypts_js_message():
+    cypher = base64.b64decode(SIMPLE["cypher_text"])
+    for _, secret in keys():
+        assert private_box.decrypt(cypher, secret) == SIMPLE["msg"].encode()
+
+
+def test_round_trip():
+    (alice_pk, alice_sk), (bob_pk, bob_sk) = keys()[:2]
+    cypher = private_box.encrypt(b"hello from python", [alice_pk, bob_pk])
+    assert isinstance(cypher, bytes)
+    assert private_box.decrypt(cypher, alice_sk) == b"hello from python"
+    assert private_box.decrypt(cypher, bob_sk) == b"hello from python"
+
+
+def test_not_a_recipient_is_none():
+    (alice_pk, _), (_, bob_sk) = keys()[:2]
+    cypher = private_box.encrypt(b"hi", [alice_pk])
+    assert private_box.decrypt(cypher, bob_sk) is None
+    assert private_box.decrypt(b"too short", bob_sk) is None
+
+
+def test_bad_keys_raise():
+    alice_pk, alice_sk = keys()[0]
+    assert raises_value_error(lambda: private_box.encrypt(b"hi", [alice_pk[:31]]))
+    assert raises_value_error(lambda: private_box.encrypt(b"hi", [alice_pk + b"\0"]))
+    assert raises_value_error(lambda: private_box.encrypt(b"hi", []))
+    assert raises_value_error(lambda: private_box.decrypt(b"hi", alice_sk[:32]))
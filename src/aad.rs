@@ -0,0 +1,119 @@
+//! Messages whose body also authenticates some public "additional data".
+//!
+//! The header and key slots are exactly the same as a normal message, but the
+//! body is sealed with XChaCha20-Poly1305 (libsodium's
+//! `crypto_aead_xchacha20poly1305_ietf`) instead of `crypto_secretbox`. This
+//! is an extension to the private-box format: the javascript implementation,
+//! and `private_box::decrypt`, can't open these messages, and
+//! `decrypt_with_aad` can't open normal ones.
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+use ssb_crypto::{Keypair, PublicKey};
+
+use crate::private_box::{body_offset, encrypted_size, seal_header, EncryptError, PrivateBox};
+
+/// Like `encrypt`, but the body is also bound to `aad`, which isn't encrypted
+/// or included in the message. The message can only be decrypted by passing
+/// the same `aad` to `decrypt_with_aad`.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_with_aad, encrypt_with_aad, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let cypher = encrypt_with_aad(b"hello!", &[alice.public], b"post").unwrap();
+///
+/// assert_eq!(decrypt_with_aad(&cypher, &alice, b"post").unwrap(), b"hello!");
+/// assert!(decrypt_with_aad(&cypher, &alice, b"vote").is_none());
+/// ```
+pub fn encrypt_with_aad(
+    plaintext: &[u8],
+    recipients: &[PublicKey],
+    aad: &[u8],
+) -> Result<Vec<u8>, EncryptError> {
+    PrivateBox::check_plaintext_len(plaintext.len())?;
+    PrivateBox::new().check_recipients(recipients.len())?;
+
+    let mut out = vec![0; encrypted_size(plaintext, recipients)];
+    let (nonce, key, rest) = seal_header(recipients, &mut out)?;
+    let (tag_buf, text) = rest.split_at_mut(Hmac::SIZE);
+    text.copy_from_slice(plaintext);
+
+    let tag = cipher(&key)
+        .encrypt_in_place_detached(nonce_of(&nonce), aad, text)
+        .expect("message is too long to seal");
+    tag_buf.copy_from_slice(&tag);
+    Ok(out)
+}
+
+/// Decrypt a message made by `encrypt_with_aad`. Returns `None` if it isn't
+/// addressed to `keypair`, or `aad` isn't what it was encrypted with.
+pub fn decrypt_with_aad(cyphertext: &[u8], keypair: &Keypair, aad: &[u8]) -> Option<Vec<u8>> {
+    let msg_key = PrivateBox::new().decrypt_key(cyphertext, keypair)?;
+    let (nonce, offset) = body_offset(cyphertext, &msg_key).ok()?;
+    let (tag, body) = cyphertext[offset..].split_at(Hmac::SIZE);
+
+    let mut out = body.to_vec();
+    cipher(&msg_key.key)
+        .decrypt_in_place_detached(nonce_of(&nonce), aad, &mut out, Tag::from_slice(tag))
+        .ok()?;
+    Some(out)
+}
+
+fn cipher(key: &Key) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(&key.0.into())
+}
+
+fn nonce_of(nonce: &Nonce) -> &XNonce {
+    XNonce::from_slice(&nonce.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::{decrypt, encrypt};
+
+    #[test]
+    fn aad_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let eve = Keypair::generate();
+        let recps = [alice.public, bob.public];
+
+        let cypher = encrypt_with_aad(&[0, 1, 2], &recps, b"type:post").unwrap();
+        assert_eq!(cypher.len(), encrypted_size(&[0, 1, 2], &recps));
+        for k in [&alice, &bob].iter() {
+            assert_eq!(
+                decrypt_with_aad(&cypher, k, b"type:post").unwrap(),
+                [0, 1, 2]
+            );
+        }
+        assert!(decrypt_with_aad(&cypher, &eve, b"type:post").is_none());
+        assert!(decrypt_with_aad(&cypher, &alice, b"type:vote").is_none());
+        assert!(decrypt_with_aad(&cypher, &alice, b"").is_none());
+
+        let mut tampered = cypher.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(decrypt_with_aad(&tampered, &alice, b"type:post").is_none());
+    }
+
+    #[test]
+    fn aad_and_classic_messages_dont_mix() {
+        let alice = Keypair::generate();
+
+        let with_aad = encrypt_with_aad(&[0, 1, 2], &[alice.public], b"").unwrap();
+        assert!(decrypt(&with_aad, &alice).is_none());
+
+        let classic = encrypt(&[0, 1, 2], &[alice.public]).unwrap();
+        assert!(decrypt_with_aad(&classic, &alice, b"").is_none());
+    }
+
+    #[test]
+    fn aad_checks_recipients() {
+        assert_eq!(
+            encrypt_with_aad(&[0], &[], b""),
+            Err(EncryptError::NoRecipients)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Messages whose plaintext goes through an all-or-nothing transform before
+//! it's encrypted, so none of it can be recovered without all of the body.
+//!
+//! This is Rivest's package transform, with HMAC-SHA-512-256 as both the
+//! keystream and the hash. A random package key `k` is drawn, and each 32
+//! byte block `m_i` of the plaintext is XORed with `HMAC(k, i)`, `i` being
+//! the block's index as a big-endian u64. The hash of those blocks, `HMAC`
+//! under a key of all zeros, is XORed with `k` and appended. Undoing it
+//! needs the hash of every block, so `k`, and with it any of the plaintext,
+//! can only be recovered once the whole package is present. The body is 32
+//! bytes longer than the plaintext, and packing and unpacking each cost two
+//! HMACs per block.
+//!
+//! The body's mac already stops `decrypt` returning any of a body that was
+//! cut short or changed. The transform is for when that's not enough: a
+//! body that's opened with a key that leaked, or by code that doesn't wait
+//! for the mac, still gives up nothing unless it's whole.
+//!
+//! These are ordinary private-box messages, so `decrypt` opens them too, but
+//! returns the transformed body; `decrypt_aont` undoes the transform.
+use rand_core::{OsRng, RngCore};
+use ssb_crypto::{Keypair, NetworkKey, PublicKey};
+
+use crate::private_box::{decrypt, encrypt, EncryptError};
+
+const BLOCK_SIZE: usize = 32;
+
+/// How many bytes longer than its plaintext a body from `encrypt_aont` is.
+pub const AONT_OVERHEAD: usize = BLOCK_SIZE;
+
+/// Like `encrypt`, but applies an all-or-nothing transform to the plaintext
+/// first. Decrypt it with `decrypt_aont`.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_aont, encrypt_aont, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let cypher = encrypt_aont(b"all or nothing", &[alice.public]).unwrap();
+///
+/// assert_eq!(decrypt_aont(&cypher, &alice).unwrap(), b"all or nothing");
+/// ```
+pub fn encrypt_aont(plaintext: &[u8], recipients: &[PublicKey]) -> Result<Vec<u8>, EncryptError> {
+    let mut key = [0; 32];
+    OsRng.fill_bytes(&mut key);
+    encrypt(&package(plaintext, &key), recipients)
+}
+
+/// Decrypt a message made by `encrypt_aont`, and undo the transform.
+/// Returns `None` if the message isn't addressed to `keypair`, or its body
+/// is too short to have been transformed.
+pub fn decrypt_aont(cyphertext: &[u8], keypair: &Keypair) -> Option<Vec<u8>> {
+    unpackage(decrypt(cyphertext, keypair)?)
+}
+
+fn package(plaintext: &[u8], key: &[u8; 32]) -> Vec<u8> {
+    let mut body = plaintext.to_vec();
+    apply_keystream(&mut body, key);
+    let hash = NetworkKey([0; 32]).authenticate(&body).0;
+    body.extend(key.iter().zip(&hash).map(|(k, h)| k ^ h));
+    body
+}
+
+fn unpackage(mut body: Vec<u8>) -> Option<Vec<u8>> {
+    let split = body.len().checked_sub(AONT_OVERHEAD)?;
+    let (blocks, masked) = body.split_at(split);
+    let hash = NetworkKey([0; 32]).authenticate(blocks).0;
+    let mut key = [0; 32];
+    for (k, (m, h)) in key.iter_mut().zip(masked.iter().zip(&hash)) {
+        *k = m ^ h;
+    }
+    body.truncate(split);
+    apply_keystream(&mut body, &key);
+    Some(body)
+}
+
+/// XOR each block of `data` with `HMAC(key, i)`.
+fn apply_keystream(data: &mut [u8], key: &[u8; 32]) {
+    // ssb-crypto's only HMAC takes its key as a `NetworkKey`.
+    let key = NetworkKey(*key);
+    for (i, block) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+        let pad = key.authenticate(&(i as u64).to_be_bytes()).0;
+        for (b, p) in block.iter_mut().zip(&pad) {
+            *b ^= p;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryInto;
+
+    #[test]
+    fn aont_round_trip() {
+        let alice = Keypair::generate();
+        for len in [0, 1, 31, 32, 33, 1000] {
+            let text: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let cypher = encrypt_aont(&text, &[alice.public]).unwrap();
+            assert_eq!(decrypt(&cypher, &alice).unwrap().len(), len + AONT_OVERHEAD);
+            assert_eq!(decrypt_aont(&cypher, &alice).unwrap(), text);
+            assert!(decrypt_aont(&cypher, &Keypair::generate()).is_none());
+        }
+
+        let short = encrypt(&[0; AONT_OVERHEAD - 1], &[alice.public]).unwrap();
+        assert!(decrypt_aont(&short, &alice).is_none());
+    }
+
+    #[test]
+    fn any_changed_byte_destroys_every_block() {
+        let text = [0x5a; 4 * BLOCK_SIZE];
+        let body = package(&text, &[3; 32]);
+        assert_eq!(unpackage(body.clone()).unwrap(), text);
+
+        for i in 0..body.len() {
+            let mut changed = body.clone();
+            changed[i] ^= 1;
+            let out = unpackage(changed).unwrap();
+            for (got, want) in out.chunks(BLOCK_SIZE).zip(text.chunks(BLOCK_SIZE)) {
+                assert_ne!(got, want, "flipping byte {} left a block intact", i);
+            }
+        }
+
+        // The body mac already rejects the message as a whole.
+        let alice = Keypair::generate();
+        let mut cypher = encrypt_aont(&text, &[alice.public]).unwrap();
+        let last = cypher.len() - 1;
+        cypher[last] ^= 1;
+        assert!(decrypt_aont(&cypher, &alice).is_none());
+    }
+
+    #[test]
+    fn keystream_blocks_are_indexed() {
+        let mut a = [0; 2 * BLOCK_SIZE];
+        apply_keystream(&mut a, &[1; 32]);
+        let (first, second) = a.split_at(BLOCK_SIZE);
+        assert_ne!(first, second);
+        let first: [u8; 32] = first.try_into().unwrap();
+        assert_eq!(
+            first,
+            NetworkKey([1; 32]).authenticate(&0u64.to_be_bytes()).0
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::private_box::{decrypt, encrypt, EncryptError};
+use ssb_crypto::{Keypair, PublicKey};
+
+/// Like `encrypt`, but returns the message as a (standard alphabet,
+/// padded) base64 string.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_from_base64, encrypt_to_base64, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let s = encrypt_to_base64(b"hello!", &[alice.public]).unwrap();
+/// assert_eq!(decrypt_from_base64(&s, &alice).unwrap(), b"hello!");
+/// ```
+pub fn encrypt_to_base64(
+    plaintext: &[u8],
+    recipients: &[PublicKey],
+) -> Result<String, EncryptError> {
+    Ok(base64::encode_config(
+        encrypt(plaintext, recipients)?,
+        base64::STANDARD,
+    ))
+}
+
+/// Like `decrypt`, but takes a base64 encoded message.
+/// Both the standard and url-safe alphabets are accepted, as is the
+/// `.box` suffix that ssb appends to private messages.
+///
+/// Returns `None` if `s` isn't valid base64, or the message
+/// can't be decrypted with `keypair`.
+pub fn decrypt_from_base64(s: &str, keypair: &Keypair) -> Option<Vec<u8>> {
+    decrypt(&decode(s)?, keypair)
+}
+
+/// Decode a base64 message, in either alphabet, with or without a `.box` suffix.
+pub(crate) fn decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.strip_suffix(".box").unwrap_or(s);
+    base64::decode_config(s, base64::STANDARD)
+        .or_else(|_| base64::decode_config(s, base64::URL_SAFE))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trip() {
+        let alice = Keypair::generate();
+        let s = encrypt_to_base64(&[0, 1, 2], &[alice.public]).unwrap();
+
+        assert_eq!(decrypt_from_base64(&s, &alice).unwrap(), [0, 1, 2]);
+        assert_eq!(
+            decrypt_from_base64(&format!("{}.box", s), &alice).unwrap(),
+            [0, 1, 2]
+        );
+        assert!(decrypt_from_base64(&s, &Keypair::generate()).is_none());
+    }
+
+    #[test]
+    fn base64_url_safe() {
+        let alice = Keypair::generate();
+        // Find a message whose encoding differs between the alphabets.
+        let cypher = loop {
+            let c = crate::encrypt(&[0; 64], &[alice.public]).unwrap();
+            if base64::encode(&c).contains(['+', '/']) {
+                break c;
+            }
+        };
+        let s = base64::encode_config(&cypher, base64::URL_SAFE);
+        assert_eq!(decrypt_from_base64(&s, &alice).unwrap(), [0; 64]);
+    }
+
+    #[test]
+    fn invalid_base64_is_none() {
+        let alice = Keypair::generate();
+        assert!(decrypt_from_base64("", &alice).is_none());
+        assert!(decrypt_from_base64("not base64!", &alice).is_none());
+        assert!(decrypt_from_base64("aGVsbG8=", &alice).is_none());
+    }
+}
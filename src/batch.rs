@@ -0,0 +1,66 @@
+use rayon::prelude::*;
+use ssb_crypto::Keypair;
+
+use crate::private_box::decrypt;
+
+/// Decrypt many messages at once, spread across rayon's thread pool.
+/// The result for each message is what `decrypt` would return for it, in
+/// the same order as `messages`.
+///
+/// Each message is still scanned one slot at a time; it's the messages that
+/// are decrypted in parallel, so this pays off for large batches rather than
+/// single messages.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_batch, encrypt, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let a = encrypt(b"one", &[alice.public]).unwrap();
+/// let b = encrypt(b"two", &[Keypair::generate().public]).unwrap();
+///
+/// let out = decrypt_batch(&[&a, &b], &alice);
+/// assert_eq!(out, vec![Some(b"one".to_vec()), None]);
+/// ```
+pub fn decrypt_batch(messages: &[&[u8]], keypair: &Keypair) -> Vec<Option<Vec<u8>>> {
+    messages
+        .par_iter()
+        .map(|cyphertext| decrypt(cyphertext, keypair))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt;
+
+    #[test]
+    fn batch_matches_sequential() {
+        let alice = Keypair::generate();
+        let others: Vec<Keypair> = (0..7).map(|_| Keypair::generate()).collect();
+
+        let messages: Vec<Vec<u8>> = (0..500)
+            .map(|i| {
+                let mut recps: Vec<_> = others.iter().map(|k| k.public).collect();
+                if i % 3 != 0 {
+                    // Put alice in a different slot each time.
+                    recps.insert(i % 8, alice.public);
+                }
+                recps.truncate(8);
+                let mut cypher = encrypt(&vec![i as u8; i], &recps).unwrap();
+                if i % 7 == 0 {
+                    cypher.truncate(100);
+                }
+                cypher
+            })
+            .collect();
+        let refs: Vec<&[u8]> = messages.iter().map(|m| &m[..]).collect();
+
+        let sequential: Vec<_> = refs.iter().map(|m| decrypt(m, &alice)).collect();
+        assert_eq!(decrypt_batch(&refs, &alice), sequential);
+        assert!(sequential.iter().any(|m| m.is_some()));
+        assert!(sequential.iter().any(|m| m.is_none()));
+
+        assert!(decrypt_batch(&[], &alice).is_empty());
+    }
+}
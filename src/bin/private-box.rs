@@ -0,0 +1,123 @@
+//! Encrypt and decrypt private-box messages from the command line.
+//! Built with the `cli` feature.
+
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use private_box::{
+    decrypt_from_base64, encrypt_to_base64, public_key_from_hex, secret_key_from_hex, Keypair,
+    PublicKey,
+};
+
+#[derive(Parser)]
+#[command(name = "private-box", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt stdin, writing the base64 message to stdout.
+    Encrypt {
+        /// A recipient's hex public key. Can be given up to 8 times.
+        #[arg(long = "recipient", required = true, value_parser = parse_public_key)]
+        recipients: Vec<PublicKey>,
+    },
+    /// Decrypt a base64 message from stdin, writing the plaintext to stdout.
+    Decrypt {
+        /// Your hex secret key: the 32 byte seed followed by the public key.
+        #[arg(long, value_parser = parse_keypair)]
+        secret: Keypair,
+    },
+}
+
+fn parse_public_key(s: &str) -> Result<PublicKey, String> {
+    public_key_from_hex(s)
+        .map(PublicKey)
+        .map_err(|e| e.to_string())
+}
+
+fn parse_keypair(s: &str) -> Result<Keypair, String> {
+    let bytes = secret_key_from_hex(s).map_err(|e| e.to_string())?;
+    Keypair::from_slice(&bytes).ok_or_else(|| "not an ed25519 secret key".to_string())
+}
+
+/// Run `command` on `input`, returning what to write to stdout.
+fn run(command: &Command, input: &[u8]) -> Result<Vec<u8>, String> {
+    match command {
+        Command::Encrypt { recipients } => encrypt_to_base64(input, recipients)
+            .map(|mut s| {
+                s.push('\n');
+                s.into_bytes()
+            })
+            .map_err(|e| format!("can't encrypt: {}", e)),
+        Command::Decrypt { secret } => {
+            let s = std::str::from_utf8(input).map_err(|_| "message isn't base64")?;
+            decrypt_from_base64(s.trim(), secret)
+                .ok_or_else(|| "can't decrypt the message with this key".to_string())
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let mut input = Vec::new();
+    if let Err(e) = io::stdin().read_to_end(&mut input) {
+        eprintln!("private-box: can't read stdin: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    match run(&cli.command, &input) {
+        Ok(out) => match io::stdout().write_all(&out) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("private-box: can't write stdout: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Err(e) => {
+            eprintln!("private-box: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret_hex(kp: &Keypair) -> String {
+        let mut bytes = kp.secret.0.to_vec();
+        bytes.extend_from_slice(&kp.public.0);
+        hex::encode(bytes)
+    }
+
+    #[test]
+    fn cli_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let pk = hex::encode(alice.public.0);
+
+        let cli = Cli::try_parse_from(["private-box", "encrypt", "--recipient", &pk]).unwrap();
+        let msg = run(&cli.command, b"hello!").unwrap();
+        assert!(msg.ends_with(b"\n"));
+
+        let sk = secret_hex(&alice);
+        let cli = Cli::try_parse_from(["private-box", "decrypt", "--secret", &sk]).unwrap();
+        assert_eq!(run(&cli.command, &msg).unwrap(), b"hello!");
+
+        let sk = secret_hex(&bob);
+        let cli = Cli::try_parse_from(["private-box", "decrypt", "--secret", &sk]).unwrap();
+        assert!(run(&cli.command, &msg).is_err());
+    }
+
+    #[test]
+    fn cli_rejects_bad_keys() {
+        assert!(Cli::try_parse_from(["private-box", "encrypt"]).is_err());
+        assert!(Cli::try_parse_from(["private-box", "encrypt", "--recipient", "abc"]).is_err());
+        assert!(Cli::try_parse_from(["private-box", "decrypt", "--secret", "00"]).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Messages whose plaintext is compressed before it's encrypted.
+//!
+//! The body starts with a one byte marker saying how the rest of it is
+//! stored: `0` for the plaintext as it is, `1` for the plaintext compressed
+//! with deflate (RFC 1951). A plaintext is only stored compressed if that
+//! makes it shorter, so already-compressed or random data costs just the
+//! marker byte.
+//!
+//! These are ordinary private-box messages, so `decrypt` opens them too, but
+//! returns the marked body; `decrypt_compressed` reads the marker and
+//! inflates the body if need be. A body without a marker, from plain
+//! `encrypt`, can't be told apart from one with, so messages from both
+//! shouldn't be mixed unless something else says which is which.
+//!
+//! Compression makes a message's length depend on its contents, not just on
+//! how long it is. If an attacker can get their own text into a plaintext
+//! alongside a secret, and see the size of the message, they may be able to
+//! learn the secret a guess at a time (as in the CRIME attack on TLS). Don't
+//! compress plaintexts like that.
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use ssb_crypto::{Keypair, PublicKey};
+
+use crate::private_box::{decrypt, encrypt, EncryptError};
+
+const STORED: u8 = 0;
+const DEFLATED: u8 = 1;
+
+/// Like `encrypt`, but compresses the plaintext first, if that makes it
+/// shorter. Decrypt it with `decrypt_compressed`.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_compressed, encrypt, encrypt_compressed, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let text = "all work and no play ".repeat(100);
+/// let cypher = encrypt_compressed(text.as_bytes(), &[alice.public]).unwrap();
+///
+/// assert!(cypher.len() < encrypt(text.as_bytes(), &[alice.public]).unwrap().len());
+/// assert_eq!(decrypt_compressed(&cypher, &alice).unwrap(), text.as_bytes());
+/// ```
+pub fn encrypt_compressed(
+    plaintext: &[u8],
+    recipients: &[PublicKey],
+) -> Result<Vec<u8>, EncryptError> {
+    let mut body = DeflateEncoder::new(vec![DEFLATED], Compression::default());
+    body.write_all(plaintext)
+        .expect("writing to a Vec can't fail");
+    let mut body = body.finish().expect("writing to a Vec can't fail");
+
+    if body.len() > plaintext.len() {
+        body.clear();
+        body.push(STORED);
+        body.extend_from_slice(plaintext);
+    }
+    encrypt(&body, recipients)
+}
+
+/// Decrypt a message made by `encrypt_compressed`, inflating its body if it
+/// was compressed. Returns `None` if the message isn't addressed to
+/// `keypair`, or its body isn't marked, or doesn't inflate.
+///
+/// Deflate can't expand data more than about 1032 times, so that's the most
+/// memory a message can make this use, relative to its own length.
+pub fn decrypt_compressed(cyphertext: &[u8], keypair: &Keypair) -> Option<Vec<u8>> {
+    let body = decrypt(cyphertext, keypair)?;
+    let (marker, rest) = body.split_first()?;
+    match *marker {
+        STORED => Some(rest.to_vec()),
+        DEFLATED => {
+            let mut out = Vec::new();
+            DeflateDecoder::new(rest).read_to_end(&mut out).ok()?;
+            Some(out)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::encrypted_len;
+    use rand_core::{OsRng, RngCore};
+
+    #[test]
+    fn compressed_round_trip() {
+        let alice = Keypair::generate();
+        let text = b"private-box private-box private-box ".repeat(50);
+
+        let cypher = encrypt_compressed(&text, &[alice.public]).unwrap();
+        assert!(cypher.len() < encrypted_len(text.len(), 1) / 10);
+        assert_eq!(decrypt(&cypher, &alice).unwrap()[0], DEFLATED);
+        assert_eq!(decrypt_compressed(&cypher, &alice).unwrap(), text);
+        assert!(decrypt_compressed(&cypher, &Keypair::generate()).is_none());
+
+        let empty = encrypt_compressed(&[], &[alice.public]).unwrap();
+        assert_eq!(decrypt_compressed(&empty, &alice).unwrap(), b"");
+    }
+
+    #[test]
+    fn incompressible_data_is_stored() {
+        let alice = Keypair::generate();
+        let mut random = vec![0; 4096];
+        OsRng.fill_bytes(&mut random);
+
+        let cypher = encrypt_compressed(&random, &[alice.public]).unwrap();
+        assert_eq!(cypher.len(), encrypted_len(random.len() + 1, 1));
+        let body = decrypt(&cypher, &alice).unwrap();
+        assert_eq!(body[0], STORED);
+        assert_eq!(body[1..], random[..]);
+        assert_eq!(decrypt_compressed(&cypher, &alice).unwrap(), random);
+    }
+
+    #[test]
+    fn bad_bodies_are_rejected() {
+        let alice = Keypair::generate();
+        for body in [&[][..], &[2, 0, 1], &[DEFLATED, 0xff, 0xff, 0xff]] {
+            let cypher = encrypt(body, &[alice.public]).unwrap();
+            assert!(decrypt_compressed(&cypher, &alice).is_none(), "{:?}", body);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Converting ed25519 keys, which ssb identities are, to the curve25519 keys
+//! private-box does Diffie-Hellman with.
+//!
+//! `encrypt` and `decrypt` convert keys themselves, so these are only needed
+//! to use the converted keys for something else. They give the same results
+//! as libsodium's `crypto_sign_ed25519_pk_to_curve25519` and
+//! `crypto_sign_ed25519_sk_to_curve25519`.
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use ssb_crypto::ephemeral::sk_to_curve;
+use ssb_crypto::{Keypair, SecretKey};
+
+/// Convert an ed25519 public key to the curve25519 key with the same
+/// secret. Returns `None`, as libsodium fails, if `pk` isn't a point on the
+/// curve, or is of small order, or has a small order component, as a
+/// Diffie-Hellman secret with any of those can be guessed.
+///
+/// # Example
+/// ```
+/// use private_box::{ed25519_pk_to_curve, Keypair};
+///
+/// let alice = Keypair::generate();
+/// assert!(ed25519_pk_to_curve(&alice.public.0).is_some());
+/// assert!(ed25519_pk_to_curve(&[0; 32]).is_none());
+/// ```
+pub fn ed25519_pk_to_curve(pk: &[u8; 32]) -> Option<[u8; 32]> {
+    let point = CompressedEdwardsY(*pk).decompress()?;
+    if point.is_small_order() || !point.is_torsion_free() {
+        return None;
+    }
+    Some(point.to_montgomery().to_bytes())
+}
+
+/// Convert an ed25519 secret key, in libsodium's 64 byte layout (the seed,
+/// then the public key), to the curve25519 secret key whose public key is
+/// `ed25519_pk_to_curve` of the same public key.
+///
+/// libsodium only looks at the seed. This checks the public key too, and
+/// returns `None` if it isn't the seed's, as then the key has been cut or
+/// spliced from the wrong bytes.
+///
+/// # Example
+/// ```
+/// use private_box::{ed25519_sk_to_curve, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let mut sk = [0; 64];
+/// sk[..32].copy_from_slice(&alice.secret.0);
+/// sk[32..].copy_from_slice(&alice.public.0);
+///
+/// assert!(ed25519_sk_to_curve(&sk).is_some());
+/// sk[63] ^= 1;
+/// assert!(ed25519_sk_to_curve(&sk).is_none());
+/// ```
+pub fn ed25519_sk_to_curve(sk: &[u8; 64]) -> Option<[u8; 32]> {
+    let (seed, public) = sk.split_at(32);
+    if Keypair::from_seed(seed)?.public.0 != public {
+        return None;
+    }
+    let mut secret = SecretKey([0; 32]);
+    secret.0.copy_from_slice(seed);
+    sk_to_curve(&secret).map(|curve| curve.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curve25519_dalek::constants::EIGHT_TORSION;
+
+    fn unhex(s: &str) -> [u8; 32] {
+        let mut out = [0; 32];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    // From libsodium's test/default/ed25519_convert.c.
+    const SEED: &str = "421151a459faeade3d247115f94aedae42318124095afabe4d1451a559faedee";
+    const CURVE_PK: &str = "f1814f0e8ff1043d8a44d25babff3cedcae6c22c3edaa48f857ae70de2baae50";
+    const CURVE_SK: &str = "8052030376d47112be7f73ed7a019293dd12ad910b654455798b4667d73de166";
+
+    #[test]
+    fn libsodium_vectors() {
+        let keypair = Keypair::from_seed(&unhex(SEED)).unwrap();
+        let mut sk = [0; 64];
+        sk[..32].copy_from_slice(&keypair.secret.0);
+        sk[32..].copy_from_slice(&keypair.public.0);
+
+        assert_eq!(
+            ed25519_pk_to_curve(&keypair.public.0),
+            Some(unhex(CURVE_PK))
+        );
+        assert_eq!(ed25519_sk_to_curve(&sk), Some(unhex(CURVE_SK)));
+    }
+
+    #[test]
+    fn unsafe_public_keys_are_refused() {
+        // Not on the curve.
+        let mut not_a_point = [0; 32];
+        not_a_point[0] = 2;
+        assert_eq!(ed25519_pk_to_curve(&not_a_point), None);
+
+        for torsion in EIGHT_TORSION.iter() {
+            // Small order.
+            assert_eq!(ed25519_pk_to_curve(&torsion.compress().0), None);
+        }
+
+        // A real key, but with a small order component added.
+        let keypair = Keypair::generate();
+        let point = CompressedEdwardsY(keypair.public.0).decompress().unwrap();
+        let mixed = (point + EIGHT_TORSION[1]).compress().0;
+        assert_eq!(ed25519_pk_to_curve(&mixed), None);
+    }
+
+    #[test]
+    fn converted_keys_agree() {
+        let keypair = Keypair::generate();
+        let mut sk = [0; 64];
+        sk[..32].copy_from_slice(&keypair.secret.0);
+        sk[32..].copy_from_slice(&keypair.public.0);
+
+        let curve_sk = ed25519_sk_to_curve(&sk).unwrap();
+        let curve_pk = ed25519_pk_to_curve(&keypair.public.0).unwrap();
+        let derived = (curve25519_dalek::constants::X25519_BASEPOINT
+            * curve25519_dalek::scalar::Scalar::from_bits(curve_sk))
+        .to_bytes();
+        assert_eq!(derived, curve_pk);
+    }
+}
@@ -0,0 +1,133 @@
+use alloc::vec::Vec;
+
+use ssb_crypto::ephemeral::{sk_to_curve, EphSecretKey};
+
+use crate::private_box::{curve_slot_key, DecryptError, PrivateBox};
+use crate::scheme::ClassicScheme;
+use crate::secret_key::SecretKey;
+
+/// Decrypts many messages with the same secret key.
+///
+/// Each call to `decrypt` converts the ed25519 secret key to curve25519
+/// before it can derive the secret shared with a message's one-time key. A
+/// `Decryptor` does that once, in `new`, and keeps the converted key until
+/// it's dropped, when it's zeroed. Every message still needs a scalar
+/// multiplication of its own, as each has a different one-time key.
+///
+/// Not to be confused with `stream::Decryptor`, which reads one streamed
+/// message.
+///
+/// # Example
+/// ```
+/// use private_box::{encrypt, Decryptor, Keypair, SecretKey};
+///
+/// let alice = Keypair::generate();
+/// let decryptor = Decryptor::new(&SecretKey::from(&alice));
+///
+/// for msg in [&b"one"[..], &b"two"[..]].iter() {
+///     let cypher = encrypt(msg, &[alice.public]).unwrap();
+///     assert_eq!(&decryptor.decrypt(&cypher).unwrap(), msg);
+/// }
+/// ```
+pub struct Decryptor {
+    // Zeroed on drop.
+    curve_sk: EphSecretKey,
+    pbox: PrivateBox,
+}
+
+impl Decryptor {
+    /// A decryptor for messages to the keypair with `secret_key`, using the
+    /// limits of `PrivateBox::new()`.
+    pub fn new(secret_key: &SecretKey) -> Decryptor {
+        Decryptor::with_box(secret_key, PrivateBox::new())
+    }
+
+    /// Like `new`, but decrypting as `pbox` does, e.g. trying only as many
+    /// key slots as its `max_slots`.
+    pub fn with_box(secret_key: &SecretKey, pbox: PrivateBox) -> Decryptor {
+        Decryptor {
+            curve_sk: sk_to_curve(&ssb_crypto::SecretKey(secret_key.0))
+                .expect("any ed25519 secret key converts"),
+            pbox,
+        }
+    }
+
+    /// Like `private_box::decrypt`, with this decryptor's key.
+    pub fn decrypt(&self, cyphertext: &[u8]) -> Option<Vec<u8>> {
+        self.decrypt_checked(cyphertext).ok()
+    }
+
+    /// Like `private_box::decrypt_checked`, with this decryptor's key.
+    pub fn decrypt_checked(&self, cyphertext: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        self.pbox
+            .decrypt_with_key_key::<ClassicScheme, _>(cyphertext, |onetime_pk| {
+                curve_slot_key(&self.curve_sk, onetime_pk)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::{decrypt, decrypt_checked, encrypt};
+    use ssb_crypto::Keypair;
+
+    #[test]
+    fn decrypts_many_messages() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let decryptor = Decryptor::new(&SecretKey::from(&alice));
+
+        for i in 0..100 {
+            let msg = vec![i as u8; i * 10];
+            let recps = if i % 2 == 0 {
+                vec![alice.public]
+            } else {
+                vec![bob.public, alice.public]
+            };
+            let cypher = encrypt(&msg, &recps).unwrap();
+            assert_eq!(decryptor.decrypt(&cypher).unwrap(), msg);
+            assert_eq!(decryptor.decrypt(&cypher), decrypt(&cypher, &alice));
+        }
+    }
+
+    #[test]
+    fn errors_match_decrypt_checked() {
+        let alice = Keypair::generate();
+        let decryptor = Decryptor::new(&SecretKey::from(&alice));
+
+        let other = encrypt(b"hello!", &[Keypair::generate().public]).unwrap();
+        let mut tampered = encrypt(b"hello!", &[alice.public]).unwrap();
+        *tampered.last_mut().unwrap() ^= 1;
+
+        for cypher in [&other[..], &tampered, &tampered[..100], &[]] {
+            assert_eq!(
+                decryptor.decrypt_checked(cypher),
+                decrypt_checked(cypher, &alice)
+            );
+            assert!(decryptor.decrypt(cypher).is_none());
+        }
+
+        // A one-time key of small order gives every recipient the same,
+        // all-zero, shared secret, so no slot is trusted.
+        let mut weak = encrypt(b"hello!", &[alice.public]).unwrap();
+        weak[24..56].copy_from_slice(&[0; 32]);
+        assert_eq!(
+            decryptor.decrypt_checked(&weak),
+            Err(DecryptError::NotARecipient)
+        );
+    }
+
+    #[test]
+    fn respects_max_slots() {
+        let keys: Vec<Keypair> = (0..3).map(|_| Keypair::generate()).collect();
+        let recps: Vec<_> = keys.iter().map(|k| k.public).collect();
+        let cypher = encrypt(b"hello!", &recps).unwrap();
+
+        let limited =
+            Decryptor::with_box(&SecretKey::from(&keys[2]), PrivateBox::new().max_slots(2));
+        assert!(limited.decrypt(&cypher).is_none());
+        let decryptor = Decryptor::new(&SecretKey::from(&keys[2]));
+        assert_eq!(decryptor.decrypt(&cypher).unwrap(), b"hello!");
+    }
+}
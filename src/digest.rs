@@ -0,0 +1,100 @@
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use ssb_crypto::PublicKey;
+
+use crate::private_box::{encrypt, EncryptError};
+
+/// The hashes `encrypt_and_hash_with` can address messages by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// BLAKE2b with a 32 byte digest, as libsodium's `crypto_generichash`.
+    #[default]
+    Blake2b256,
+    /// SHA-256, which ssb uses to address messages.
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Hash `bytes`.
+    pub fn digest(self, bytes: &[u8]) -> [u8; 32] {
+        match self {
+            HashAlgorithm::Blake2b256 => Blake2b::<U32>::digest(bytes).into(),
+            HashAlgorithm::Sha256 => ssb_crypto::hash(bytes).0,
+        }
+    }
+}
+
+/// Like `encrypt`, but also returns the BLAKE2b-256 hash of the message,
+/// for storing it by content address.
+///
+/// The body is sealed all at once, so it can't be hashed as it's written;
+/// the hash is taken over the finished message, while it's still in cache.
+///
+/// # Example
+/// ```
+/// use private_box::{encrypt_and_hash, HashAlgorithm, Keypair};
+///
+/// let (cypher, hash) = encrypt_and_hash(b"hello!", &[Keypair::generate().public]).unwrap();
+/// assert_eq!(hash, HashAlgorithm::Blake2b256.digest(&cypher));
+/// ```
+pub fn encrypt_and_hash(
+    plaintext: &[u8],
+    recipients: &[PublicKey],
+) -> Result<(Vec<u8>, [u8; 32]), EncryptError> {
+    encrypt_and_hash_with(plaintext, recipients, HashAlgorithm::default())
+}
+
+/// Like `encrypt_and_hash`, with the hash of your choice.
+pub fn encrypt_and_hash_with(
+    plaintext: &[u8],
+    recipients: &[PublicKey],
+    algorithm: HashAlgorithm,
+) -> Result<(Vec<u8>, [u8; 32]), EncryptError> {
+    let cypher = encrypt(plaintext, recipients)?;
+    let hash = algorithm.digest(&cypher);
+    Ok((cypher, hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt;
+    use ssb_crypto::Keypair;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn known_digests() {
+        assert_eq!(
+            hex(&HashAlgorithm::Blake2b256.digest(b"abc")),
+            "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"
+        );
+        assert_eq!(
+            hex(&HashAlgorithm::Sha256.digest(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn hash_matches_the_message() {
+        let alice = Keypair::generate();
+        let (cypher, hash) = encrypt_and_hash(&[0, 1, 2], &[alice.public]).unwrap();
+        assert_eq!(decrypt(&cypher, &alice).unwrap(), [0, 1, 2]);
+
+        let mut hasher = Blake2b::<U32>::new();
+        hasher.update(&cypher[..56]);
+        hasher.update(&cypher[56..]);
+        assert_eq!(hash[..], hasher.finalize()[..]);
+
+        let (cypher, hash) =
+            encrypt_and_hash_with(&[0, 1, 2], &[alice.public], HashAlgorithm::Sha256).unwrap();
+        assert_eq!(hash, ssb_crypto::hash(&cypher).0);
+
+        assert_eq!(
+            encrypt_and_hash(&[0, 1, 2], &[]),
+            Err(EncryptError::NoRecipients)
+        );
+    }
+}
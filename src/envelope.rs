@@ -0,0 +1,237 @@
+//! Many messages to the same recipients, sharing one header.
+//!
+//! An envelope starts with the same header as a normal private-box message
+//! (nonce, one-time public key and a key slot for each recipient), so the
+//! recipients are only paid for once. It's followed by a sealed count of the
+//! bodies, and then each body with its length:
+//!
+//! ```text
+//! nonce (24) | one-time public key (32) | key slots (49 * recipients) | count | body | body | ...
+//!
+//! count = mac (16) | encrypted body count (u32, big-endian)
+//! body  = length (u32, big-endian) | mac (16) | encrypted plaintext (length bytes)
+//! ```
+//!
+//! Everything after the header is sealed with the body key. The count is
+//! sealed with the header nonce plus 1, treating the nonce as a little-endian
+//! number, and body `i` (counting from zero) with the nonce plus `i + 2`, so
+//! bodies can't be reordered, and the count means they can't be dropped.
+//!
+//! This is an extension of the private-box format: an envelope can only be
+//! opened with `decrypt_envelope`, not with `private_box::decrypt`, and other
+//! private-box implementations don't understand it.
+use core::convert::TryFrom;
+
+use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+use ssb_crypto::{Keypair, PublicKey};
+use zerocopy::AsBytes;
+
+use crate::private_box::{body_offset, header_len, seal_header, EncryptError, PrivateBox};
+use crate::stream::chunk_nonce;
+
+const LEN_SIZE: usize = 4;
+
+/// Builds an envelope: several bodies sealed to the same recipients, behind
+/// one header.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_envelope, Envelope, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let cypher = Envelope::new(&[alice.public])
+///     .unwrap()
+///     .add(b"one")
+///     .add(b"two")
+///     .finish();
+///
+/// let bodies = decrypt_envelope(&cypher, &alice).unwrap();
+/// assert_eq!(bodies, [b"one".to_vec(), b"two".to_vec()]);
+/// ```
+pub struct Envelope {
+    key: Key,
+    nonce: Nonce,
+    header: Vec<u8>,
+    bodies: Vec<u8>,
+    count: u32,
+}
+
+impl Envelope {
+    /// Start an envelope to `recipients`. Fails for the same reasons
+    /// `encrypt` does.
+    pub fn new(recipients: &[PublicKey]) -> Result<Envelope, EncryptError> {
+        PrivateBox::new().check_recipients(recipients.len())?;
+
+        let mut header = vec![0; header_len(recipients.len())];
+        let (nonce, key, _) = seal_header(recipients, &mut header)?;
+        Ok(Envelope {
+            key,
+            nonce,
+            header,
+            bodies: Vec::new(),
+            count: 0,
+        })
+    }
+
+    /// Seal `body` into the envelope, after the bodies already in it.
+    ///
+    /// Panics if `body` is 4GiB or longer, or the envelope already holds
+    /// `u32::MAX` bodies.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, body: &[u8]) -> Envelope {
+        let len = u32::try_from(body.len()).expect("envelope bodies must be under 4GiB");
+        assert!(self.count < u32::MAX, "too many bodies in one envelope");
+
+        let nonce = chunk_nonce(&self.nonce, self.count as u64 + 1);
+        let mut sealed = body.to_vec();
+        let hmac = self.key.seal(&mut sealed, &nonce);
+
+        self.bodies.extend_from_slice(&len.to_be_bytes());
+        self.bodies.extend_from_slice(hmac.as_bytes());
+        self.bodies.extend_from_slice(&sealed);
+        self.count += 1;
+        self
+    }
+
+    /// The finished envelope.
+    pub fn finish(self) -> Vec<u8> {
+        let mut count = self.count.to_be_bytes();
+        let hmac = self.key.seal(&mut count, &chunk_nonce(&self.nonce, 0));
+
+        let mut out = self.header;
+        out.reserve(Hmac::SIZE + LEN_SIZE + self.bodies.len());
+        out.extend_from_slice(hmac.as_bytes());
+        out.extend_from_slice(&count);
+        out.extend_from_slice(&self.bodies);
+        out
+    }
+}
+
+/// Open an envelope made by `Envelope`, returning its bodies in the order
+/// they were added. Returns `None` if it isn't addressed to `keypair`, or
+/// has been tampered with, including by dropping or reordering bodies.
+pub fn decrypt_envelope(cyphertext: &[u8], keypair: &Keypair) -> Option<Vec<Vec<u8>>> {
+    let msg_key = PrivateBox::new().decrypt_key(cyphertext, keypair)?;
+    let (nonce, offset) = body_offset(cyphertext, &msg_key).ok()?;
+    let key = &msg_key.key;
+
+    let (count_box, mut rest) = split(&cyphertext[offset..], Hmac::SIZE + LEN_SIZE)?;
+    let mut count = [0; LEN_SIZE];
+    if !key.open_attached_into(count_box, &chunk_nonce(&nonce, 0), &mut count) {
+        return None;
+    }
+    let count = u32::from_be_bytes(count);
+
+    // Every body takes at least a length and a mac, which bounds how many
+    // there can be before any are allocated for.
+    if count as usize > rest.len() / (LEN_SIZE + Hmac::SIZE) {
+        return None;
+    }
+    let mut bodies = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let (len, r) = split(rest, LEN_SIZE)?;
+        // The length isn't authenticated, so it's only trusted once the
+        // sealed body is known to be there, on 32-bit targets too.
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let (sealed, r) = split(r, len.checked_add(Hmac::SIZE)?)?;
+        rest = r;
+
+        let mut body = vec![0; len];
+        if !key.open_attached_into(sealed, &chunk_nonce(&nonce, i as u64 + 1), &mut body) {
+            return None;
+        }
+        bodies.push(body);
+    }
+
+    if rest.is_empty() {
+        Some(bodies)
+    } else {
+        None
+    }
+}
+
+fn split(bytes: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+    if bytes.len() < at {
+        None
+    } else {
+        Some(bytes.split_at(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::{decrypt, encrypted_len, overhead};
+
+    fn three_bodies(recipients: &[PublicKey]) -> Vec<u8> {
+        Envelope::new(recipients)
+            .unwrap()
+            .add(b"one")
+            .add(&[])
+            .add(&[3; 1000])
+            .finish()
+    }
+
+    #[test]
+    fn envelope_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let cypher = three_bodies(&[alice.public, bob.public]);
+
+        let expected = vec![b"one".to_vec(), vec![], vec![3; 1000]];
+        assert_eq!(decrypt_envelope(&cypher, &alice).unwrap(), expected);
+        assert_eq!(decrypt_envelope(&cypher, &bob).unwrap(), expected);
+        assert!(decrypt_envelope(&cypher, &Keypair::generate()).is_none());
+        assert!(decrypt(&cypher, &alice).is_none());
+
+        // One header for all three, rather than one each.
+        assert_eq!(cypher.len(), overhead(2) + 4 + 3 * 20 + 1003);
+        assert!(cypher.len() < 3 * encrypted_len(0, 2) + 1003);
+
+        let empty = Envelope::new(&[alice.public]).unwrap().finish();
+        assert_eq!(
+            decrypt_envelope(&empty, &alice).unwrap(),
+            Vec::<Vec<u8>>::new()
+        );
+
+        assert_eq!(Envelope::new(&[]).err(), Some(EncryptError::NoRecipients));
+    }
+
+    #[test]
+    fn tampered_envelopes_dont_open() {
+        let alice = Keypair::generate();
+        let cypher = three_bodies(&[alice.public]);
+        let start = overhead(1) - 16;
+
+        // Dropping the last body, or adding to the end.
+        let last = cypher.len() - 1000 - 20;
+        assert!(decrypt_envelope(&cypher[..last], &alice).is_none());
+        assert!(decrypt_envelope(&cypher[..cypher.len() - 1], &alice).is_none());
+        let mut longer = cypher.clone();
+        longer.push(0);
+        assert!(decrypt_envelope(&longer, &alice).is_none());
+
+        // Swapping the first two bodies.
+        let first = start + 20;
+        let mut swapped = cypher[..first].to_vec();
+        swapped.extend_from_slice(&cypher[first + 23..first + 43]);
+        swapped.extend_from_slice(&cypher[first..first + 23]);
+        swapped.extend_from_slice(&cypher[first + 43..]);
+        assert_eq!(swapped.len(), cypher.len());
+        assert!(decrypt_envelope(&swapped, &alice).is_none());
+
+        // A body length claiming far more than is there.
+        for len in [u32::MAX, u32::MAX - 15, 1001] {
+            let mut oversized = cypher.clone();
+            let at = first + 23 + 20;
+            oversized[at..at + 4].copy_from_slice(&len.to_be_bytes());
+            assert!(decrypt_envelope(&oversized, &alice).is_none());
+        }
+
+        for i in [start, start + 19, first + 2, first + 20, cypher.len() - 1] {
+            let mut corrupt = cypher.clone();
+            corrupt[i] ^= 1;
+            assert!(decrypt_envelope(&corrupt, &alice).is_none());
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::private_box::{decrypt, encrypt, wipe, EncryptError};
+use ssb_crypto::{Keypair, PublicKey};
+
+/// Why a hex string couldn't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexError {
+    /// The string has an odd number of digits.
+    OddLength,
+    /// `c`, at byte `index` of the string, isn't a hex digit.
+    InvalidCharacter { c: char, index: usize },
+    /// The string is valid hex, but decodes to `got` bytes instead of `expected`.
+    WrongLength { expected: usize, got: usize },
+}
+
+impl std::fmt::Display for HexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HexError::OddLength => write!(f, "odd number of hex digits"),
+            HexError::InvalidCharacter { c, index } => {
+                write!(f, "invalid hex character {:?} at {}", c, index)
+            }
+            HexError::WrongLength { expected, got } => {
+                write!(f, "expected {} bytes of hex, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+/// The `HexError` for an error from `hex::decode`. Not a `From` impl, as
+/// `hex::decode` never fails with `InvalidStringLength` but other callers
+/// could pass one in.
+fn hex_error(e: hex::FromHexError) -> HexError {
+    match e {
+        hex::FromHexError::OddLength => HexError::OddLength,
+        hex::FromHexError::InvalidHexCharacter { c, index } => {
+            HexError::InvalidCharacter { c, index }
+        }
+        hex::FromHexError::InvalidStringLength => unreachable!("only from decode_to_slice"),
+    }
+}
+
+/// Like `encrypt`, but returns the message as a lowercase hex string.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_from_hex, encrypt_to_hex, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let s = encrypt_to_hex(b"hello!", &[alice.public]).unwrap();
+/// assert_eq!(decrypt_from_hex(&s, &alice).unwrap(), b"hello!");
+/// ```
+pub fn encrypt_to_hex(plaintext: &[u8], recipients: &[PublicKey]) -> Result<String, EncryptError> {
+    Ok(hex::encode(encrypt(plaintext, recipients)?))
+}
+
+/// Like `decrypt`, but takes a hex encoded message, in either case.
+///
+/// Returns `None` if `s` isn't valid hex, or the message
+/// can't be decrypted with `keypair`.
+pub fn decrypt_from_hex(s: &str, keypair: &Keypair) -> Option<Vec<u8>> {
+    decrypt(&hex::decode(s).ok()?, keypair)
+}
+
+/// Read a 32 byte ed25519 public key from 64 hex digits.
+///
+/// # Example
+/// ```
+/// use private_box::{public_key_from_hex, PublicKey};
+///
+/// let s = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+/// let pk = PublicKey(public_key_from_hex(s).unwrap());
+/// ```
+pub fn public_key_from_hex(s: &str) -> Result<[u8; 32], HexError> {
+    from_hex(s)
+}
+
+/// Read a 64 byte ed25519 secret key (the seed followed by the public key,
+/// as ssb stores it) from 128 hex digits. `Keypair::from_slice` takes the result.
+pub fn secret_key_from_hex(s: &str) -> Result<[u8; 64], HexError> {
+    from_hex(s)
+}
+
+/// Decodes `s` into `N` bytes. The decoded copy is wiped before returning,
+/// as for `secret_key_from_hex` it's a secret key.
+fn from_hex<const N: usize>(s: &str) -> Result<[u8; N], HexError> {
+    let mut bytes = hex::decode(s).map_err(hex_error)?;
+    let result = match bytes.len() {
+        got if got == N => {
+            let mut out = [0; N];
+            out.copy_from_slice(&bytes);
+            Ok(out)
+        }
+        got => Err(HexError::WrongLength { expected: N, got }),
+    };
+    wipe(&mut bytes);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let alice = Keypair::generate();
+        let s = encrypt_to_hex(&[0, 1, 2], &[alice.public]).unwrap();
+
+        assert_eq!(decrypt_from_hex(&s, &alice).unwrap(), [0, 1, 2]);
+        assert_eq!(
+            decrypt_from_hex(&s.to_uppercase(), &alice).unwrap(),
+            [0, 1, 2]
+        );
+        assert!(decrypt_from_hex(&s, &Keypair::generate()).is_none());
+        assert!(decrypt_from_hex(&s[1..], &alice).is_none());
+        assert!(decrypt_from_hex("zz", &alice).is_none());
+    }
+
+    #[test]
+    fn keys_from_hex() {
+        let alice = Keypair::generate();
+        let mut secret = alice.secret.0.to_vec();
+        secret.extend_from_slice(&alice.public.0);
+
+        let pk = hex::encode(alice.public.0);
+        let sk = hex::encode(&secret);
+        for (pk, sk) in [
+            (pk.clone(), sk.clone()),
+            (pk.to_uppercase(), sk.to_uppercase()),
+        ] {
+            assert_eq!(public_key_from_hex(&pk).unwrap(), alice.public.0);
+            let kp = Keypair::from_slice(&secret_key_from_hex(&sk).unwrap()).unwrap();
+            assert_eq!(kp.public, alice.public);
+
+            let cypher = encrypt(b"hi", &[PublicKey(public_key_from_hex(&pk).unwrap())]).unwrap();
+            assert_eq!(decrypt(&cypher, &kp).unwrap(), b"hi");
+        }
+    }
+
+    #[test]
+    fn bad_hex_is_rejected() {
+        assert_eq!(public_key_from_hex("abc"), Err(HexError::OddLength));
+        assert_eq!(
+            public_key_from_hex("0g"),
+            Err(HexError::InvalidCharacter { c: 'g', index: 1 })
+        );
+        assert_eq!(
+            public_key_from_hex("abcd"),
+            Err(HexError::WrongLength {
+                expected: 32,
+                got: 2
+            })
+        );
+        assert_eq!(
+            secret_key_from_hex(&"00".repeat(32)),
+            Err(HexError::WrongLength {
+                expected: 64,
+                got: 32
+            })
+        );
+        assert_eq!(
+            public_key_from_hex(""),
+            Err(HexError::WrongLength {
+                expected: 32,
+                got: 0
+            })
+        );
+    }
+}
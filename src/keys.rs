@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::secret_key::SecretKey;
+
+#[cfg(feature = "base64")]
+use crate::private_box::wipe;
+
+/// Ways to load a `SecretKey` from outside the program.
+///
+/// `SecretKey` zeroes itself on drop, and the key is read straight into one,
+/// so no other copy of it is left behind in memory.
+pub trait SecretKeyExt: Sized {
+    /// Read a secret key from a file holding exactly its 32 raw bytes.
+    ///
+    /// Fails with `io::ErrorKind::InvalidData` if the file is any other
+    /// length, and with the underlying error if it can't be read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use private_box::{SecretKey, SecretKeyExt};
+    ///
+    /// let key = SecretKey::load_from_file("/etc/private-box/secret").unwrap();
+    /// ```
+    fn load_from_file<P: AsRef<Path>>(path: P) -> io::Result<Self>;
+
+    /// Read a secret key from the environment variable `var`, which holds
+    /// its 32 bytes in standard base64. The variable's value, and the bytes
+    /// decoded from it, are zeroed once the key has been read; the copy in
+    /// the process's environment isn't.
+    ///
+    /// Fails with `io::ErrorKind::NotFound` if `var` isn't set, and with
+    /// `io::ErrorKind::InvalidData` if it isn't a base64 key.
+    ///
+    /// Only available with the `base64` feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use private_box::{SecretKey, SecretKeyExt};
+    ///
+    /// let key = SecretKey::from_env("PRIVATE_BOX_SECRET").unwrap();
+    /// ```
+    #[cfg(feature = "base64")]
+    fn from_env(var: &str) -> io::Result<Self>;
+}
+
+impl SecretKeyExt for SecretKey {
+    fn load_from_file<P: AsRef<Path>>(path: P) -> io::Result<SecretKey> {
+        let wrong_length = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "secret key file must be exactly 32 bytes",
+            )
+        };
+
+        let mut file = File::open(path)?;
+        let mut key = SecretKey([0; 32]);
+        file.read_exact(&mut key.0).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => wrong_length(),
+            _ => e,
+        })?;
+
+        // Anything more and this isn't just a key.
+        if file.read(&mut [0])? != 0 {
+            return Err(wrong_length());
+        }
+        Ok(key)
+    }
+
+    #[cfg(feature = "base64")]
+    fn from_env(var: &str) -> io::Result<SecretKey> {
+        let invalid = |why: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("environment variable {} {}", var, why),
+            )
+        };
+
+        let mut value = match std::env::var(var) {
+            Ok(value) => value.into_bytes(),
+            Err(std::env::VarError::NotPresent) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("environment variable {} isn't set", var),
+                ))
+            }
+            Err(std::env::VarError::NotUnicode(_)) => return Err(invalid("isn't unicode")),
+        };
+
+        // 32 bytes is 44 characters of base64, with padding, and decoding
+        // that needs room for 33.
+        let mut decoded = [0; 33];
+        let result = if value.len() != 44 {
+            Err(invalid("must be a 32 byte key in base64"))
+        } else {
+            match base64::decode_config_slice(&value, base64::STANDARD, &mut decoded) {
+                Ok(32) => {
+                    let mut key = SecretKey([0; 32]);
+                    key.0.copy_from_slice(&decoded[..32]);
+                    Ok(key)
+                }
+                Ok(_) => Err(invalid("must be a 32 byte key in base64")),
+                Err(_) => Err(invalid("isn't valid base64")),
+            }
+        };
+        wipe(&mut value);
+        wipe(&mut decoded);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decrypt, encrypt, Keypair};
+    use std::path::PathBuf;
+
+    /// A path in the temp dir, removed again on drop.
+    struct TempFile(PathBuf);
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("private-box-{}-{}", name, std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn load_secret_key_from_file() {
+        let alice = Keypair::generate();
+        let file = TempFile::new("secret", &alice.secret.0);
+
+        let secret = SecretKey::load_from_file(&file.0).unwrap();
+        assert_eq!(secret.0, alice.secret.0);
+
+        let keypair = secret.keypair();
+        let cypher = encrypt(b"hi", &[alice.public]).unwrap();
+        assert_eq!(decrypt(&cypher, &keypair).unwrap(), b"hi");
+
+        assert!(std::mem::needs_drop::<SecretKey>());
+    }
+
+    #[test]
+    fn load_rejects_wrong_lengths() {
+        for (name, len) in [("empty", 0), ("short", 31), ("long", 33), ("pair", 64)] {
+            let file = TempFile::new(name, &vec![1; len]);
+            let err = SecretKey::load_from_file(&file.0).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", name);
+        }
+
+        let missing = std::env::temp_dir().join("private-box-no-such-key");
+        assert_eq!(
+            SecretKey::load_from_file(missing).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "base64")]
+    fn load_secret_key_from_env() {
+        let alice = Keypair::generate();
+        let var = "PRIVATE_BOX_TEST_SECRET";
+        std::env::set_var(var, base64::encode(alice.secret.0));
+
+        let secret = SecretKey::from_env(var).unwrap();
+        assert_eq!(secret.0, alice.secret.0);
+
+        std::env::remove_var(var);
+        let err = SecretKey::from_env(var).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(var));
+    }
+
+    #[test]
+    #[cfg(feature = "base64")]
+    fn env_rejects_malformed_keys() {
+        let var = "PRIVATE_BOX_TEST_BAD_SECRET";
+        for value in [
+            String::new(),
+            base64::encode([1; 31]),
+            base64::encode([1; 33]),
+            base64::encode([1; 64]),
+            base64::encode_config([1; 32], base64::URL_SAFE_NO_PAD),
+            "!".repeat(44),
+        ] {
+            std::env::set_var(var, &value);
+            let err = SecretKey::from_env(var).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", value);
+        }
+        std::env::remove_var(var);
+    }
+}
@@ -1,4 +1,114 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod private_box;
 pub use crate::private_box::*;
 
+mod recipients;
+pub use crate::recipients::*;
+
+mod scheme;
+pub use crate::scheme::*;
+
+mod curve;
+pub use crate::curve::*;
+
+mod decryptor;
+pub use crate::decryptor::*;
+
+mod secret_key;
+pub use crate::secret_key::*;
+
+#[cfg(feature = "std")]
+pub mod stream;
+
+#[cfg(feature = "std")]
+mod keys;
+#[cfg(feature = "std")]
+pub use crate::keys::*;
+
+#[cfg(feature = "std")]
+mod session;
+#[cfg(feature = "std")]
+pub use crate::session::*;
+
+#[cfg(feature = "std")]
+mod envelope;
+#[cfg(feature = "std")]
+pub use crate::envelope::*;
+
+#[cfg(feature = "aont")]
+mod aont;
+#[cfg(feature = "aont")]
+pub use crate::aont::*;
+
+#[cfg(feature = "base64")]
+mod b64;
+#[cfg(feature = "base64")]
+pub use crate::b64::*;
+
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(feature = "compress")]
+pub use crate::compress::*;
+
+#[cfg(feature = "hash")]
+mod digest;
+#[cfg(feature = "hash")]
+pub use crate::digest::*;
+
+#[cfg(feature = "hex")]
+mod hex_str;
+#[cfg(feature = "hex")]
+pub use crate::hex_str::*;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "mmap")]
+pub use crate::mmap::*;
+
+#[cfg(feature = "padding")]
+mod padding;
+#[cfg(feature = "padding")]
+pub use crate::padding::*;
+
+#[cfg(feature = "rayon")]
+mod batch;
+#[cfg(feature = "rayon")]
+pub use crate::batch::*;
+
+#[cfg(feature = "tokio")]
+mod offload;
+#[cfg(feature = "tokio")]
+pub use crate::offload::*;
+
+#[cfg(feature = "aad")]
+mod aad;
+#[cfg(feature = "aad")]
+pub use crate::aad::*;
+
+#[cfg(feature = "serde")]
+mod message;
+#[cfg(feature = "serde")]
+pub use crate::message::*;
+
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "python")]
+mod python;
+
+/// Messages are encrypted to ed25519 public keys and opened with the matching
+/// `Keypair`. Use `Keypair::generate()` to make a new one.
+///
+/// `PublicKey` and `SecretKey` (this crate's, below) are distinct types, so
+/// one can't be passed where the other is expected.
 pub use ssb_crypto::{Keypair, PublicKey};
+
+/// The version of `rand_core` whose traits `encrypt_with_rng` takes.
+pub use rand_core;
+
+/// The wrapper `decrypt_secure` returns plaintext in.
+#[cfg(feature = "zeroize")]
+pub use zeroize::Zeroizing;
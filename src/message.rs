@@ -0,0 +1,167 @@
+use core::fmt;
+
+use serde::de::{self, Deserialize, DeserializeOwned, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::b64;
+use crate::private_box::{decrypt, encrypt, EncryptError, MIN_CYPHERTEXT_LEN};
+use ssb_crypto::{Keypair, PublicKey};
+
+/// An encrypted private-box message, which serializes as a base64 string.
+///
+/// Deserializing fails if the string isn't valid base64, or is too short
+/// to be a private-box message.
+///
+/// # Example
+/// ```
+/// use private_box::{encrypt, Keypair, PrivateBoxMessage};
+///
+/// let alice = Keypair::generate();
+/// let msg = PrivateBoxMessage(encrypt(b"hello!", &[alice.public]).unwrap());
+///
+/// let json = serde_json::to_string(&msg).unwrap();
+/// let msg: PrivateBoxMessage = serde_json::from_str(&json).unwrap();
+/// assert_eq!(msg.decrypt(&alice).unwrap(), b"hello!");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivateBoxMessage(pub Vec<u8>);
+
+impl PrivateBoxMessage {
+    /// See `private_box::decrypt`.
+    pub fn decrypt(&self, keypair: &Keypair) -> Option<Vec<u8>> {
+        decrypt(&self.0, keypair)
+    }
+}
+
+impl Serialize for PrivateBoxMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode_config(&self.0, base64::STANDARD))
+    }
+}
+
+impl<'de> Deserialize<'de> for PrivateBoxMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(MessageVisitor)
+    }
+}
+
+struct MessageVisitor;
+
+impl Visitor<'_> for MessageVisitor {
+    type Value = PrivateBoxMessage;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a base64 encoded private-box message")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<PrivateBoxMessage, E> {
+        let bytes = b64::decode(s).ok_or_else(|| E::custom("invalid base64"))?;
+        if bytes.len() < MIN_CYPHERTEXT_LEN {
+            return Err(E::invalid_length(bytes.len(), &self));
+        }
+        Ok(PrivateBoxMessage(bytes))
+    }
+}
+
+/// Serialize `value` as CBOR, and encrypt that. Decrypt it with
+/// `decrypt_value`.
+///
+/// Panics if `value`'s `Serialize` impl fails, which the derived impls
+/// never do.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_value, encrypt_value, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let cypher = encrypt_value(&("hello!", 42), &[alice.public]).unwrap();
+///
+/// let value: (String, u32) = decrypt_value(&cypher, &alice).unwrap();
+/// assert_eq!(value, ("hello!".to_string(), 42));
+/// ```
+pub fn encrypt_value<T: Serialize + ?Sized>(
+    value: &T,
+    recipients: &[PublicKey],
+) -> Result<Vec<u8>, EncryptError> {
+    let mut body = Vec::new();
+    ciborium::ser::into_writer(value, &mut body).expect("value failed to serialize");
+    encrypt(&body, recipients)
+}
+
+/// Decrypt a message made by `encrypt_value`, and deserialize the value.
+/// Returns `None` if the message isn't addressed to `keypair`, or it doesn't
+/// hold a `T`.
+pub fn decrypt_value<T: DeserializeOwned>(cyphertext: &[u8], keypair: &Keypair) -> Option<T> {
+    let body = decrypt(cyphertext, keypair)?;
+    ciborium::de::from_reader(&body[..]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::encrypt;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        id: u32,
+        content: PrivateBoxMessage,
+    }
+
+    #[test]
+    fn serde_json_round_trip() {
+        let alice = Keypair::generate();
+        let cypher = encrypt(&[0, 1, 2], &[alice.public]).unwrap();
+        let record = Record {
+            id: 1,
+            content: PrivateBoxMessage(cypher.clone()),
+        };
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(&base64::encode(&cypher)));
+
+        let record: Record = serde_json::from_str(&json).unwrap();
+        assert_eq!(record.id, 1);
+        assert_eq!(record.content.0, cypher);
+        assert_eq!(record.content.decrypt(&alice).unwrap(), [0, 1, 2]);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Post {
+        author: String,
+        likes: u64,
+        tags: Vec<String>,
+        reply_to: Option<[u8; 32]>,
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let alice = Keypair::generate();
+        let post = Post {
+            author: "@alice".into(),
+            likes: 3,
+            tags: vec!["rust".into(), "ssb".into()],
+            reply_to: Some([7; 32]),
+        };
+
+        let cypher = encrypt_value(&post, &[alice.public]).unwrap();
+        assert_eq!(decrypt_value::<Post>(&cypher, &alice), Some(post));
+        assert_eq!(decrypt_value::<Post>(&cypher, &Keypair::generate()), None);
+
+        // Decrypted, but not a `Post`.
+        assert_eq!(decrypt_value::<u32>(&cypher, &alice), None);
+        let raw = encrypt(b"not cbor", &[alice.public]).unwrap();
+        assert_eq!(decrypt_value::<Post>(&raw, &alice), None);
+    }
+
+    #[test]
+    fn deserialize_rejects_bad_records() {
+        let short = format!("\"{}\"", base64::encode([0; MIN_CYPHERTEXT_LEN - 1]));
+        assert!(serde_json::from_str::<PrivateBoxMessage>(&short).is_err());
+        assert!(serde_json::from_str::<PrivateBoxMessage>("\"not base64!\"").is_err());
+        assert!(serde_json::from_str::<PrivateBoxMessage>("42").is_err());
+
+        let ok = format!("\"{}\"", base64::encode([0; MIN_CYPHERTEXT_LEN]));
+        assert!(serde_json::from_str::<PrivateBoxMessage>(&ok).is_ok());
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use memmap2::{Mmap, MmapMut};
+use ssb_crypto::secretbox::Hmac;
+use ssb_crypto::{Keypair, PublicKey};
+
+use crate::private_box::{header_len, PrivateBox};
+use crate::stream::{Decryptor, Encryptor, CHUNK_SIZE};
+
+/// Encrypt the file at `input` to `recipients` as a streamed message (see
+/// `private_box::stream`), writing it to `output`. Both files are
+/// memory-mapped, so neither is read into memory all at once. `output` is
+/// created, or truncated if it exists.
+///
+/// Returns the length of the message. Fails with `ErrorKind::InvalidInput`
+/// if the message can't be encrypted to `recipients`, as `encrypt` would.
+///
+/// Neither file may be changed by anything else until this returns.
+///
+/// # Example
+/// ```no_run
+/// use private_box::{decrypt_file, encrypt_file, Keypair};
+/// use std::path::Path;
+///
+/// let alice = Keypair::generate();
+/// encrypt_file(Path::new("video.mp4"), Path::new("video.box"), &[alice.public]).unwrap();
+/// decrypt_file(Path::new("video.box"), Path::new("copy.mp4"), &alice).unwrap();
+/// ```
+pub fn encrypt_file(input: &Path, output: &Path, recipients: &[PublicKey]) -> io::Result<u64> {
+    PrivateBox::new()
+        .check_recipients(recipients.len())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let plaintext = map(&File::open(input)?)?;
+    let len = header_len(recipients.len()) as u64 + stream_body_len(plaintext.len() as u64);
+
+    let file = create(output)?;
+    let result = encrypt_to(&plaintext, &file, len, recipients);
+    if result.is_err() {
+        file.set_len(0)?;
+    }
+    result
+}
+
+fn encrypt_to(
+    plaintext: &[u8],
+    file: &File,
+    len: u64,
+    recipients: &[PublicKey],
+) -> io::Result<u64> {
+    file.set_len(len)?;
+    let mut out = map_mut(file)?;
+    let mut enc = Encryptor::new(&mut out[..], recipients)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    enc.write_all(plaintext)?;
+    let written = enc.finalize()?;
+    out.flush()?;
+    Ok(written)
+}
+
+/// Decrypt a streamed message in the file at `input`, as written by
+/// `encrypt_file`, writing the plaintext to `output`. Both files are
+/// memory-mapped, as in `encrypt_file`.
+///
+/// Returns the length of the plaintext. Fails with `ErrorKind::InvalidData`
+/// if the message isn't addressed to `keypair`, or has been tampered with.
+/// Chunks are written out as they're decrypted, so if a later one fails to
+/// authenticate, `output` is truncated to nothing. If the message isn't for
+/// you, `output` isn't touched at all.
+pub fn decrypt_file(input: &Path, output: &Path, keypair: &Keypair) -> io::Result<u64> {
+    let cyphertext = map(&File::open(input)?)?;
+    let mut dec = Decryptor::new(&cyphertext[..], keypair)?;
+    let len = stream_plaintext_len(dec.get_ref().len() as u64)?;
+
+    let file = create(output)?;
+    let result = decrypt_to(&mut dec, &file, len);
+    if result.is_err() {
+        file.set_len(0)?;
+    }
+    result
+}
+
+fn decrypt_to(dec: &mut Decryptor<&[u8]>, file: &File, len: u64) -> io::Result<u64> {
+    file.set_len(len)?;
+    let mut out = map_mut(file)?;
+    let written = io::copy(dec, &mut &mut out[..])?;
+    debug_assert_eq!(written, len);
+    out.flush()?;
+    Ok(written)
+}
+
+/// The length of the streamed body of a `plaintext_len` byte message: a mac
+/// for every full chunk, and for the final, shorter, chunk.
+fn stream_body_len(plaintext_len: u64) -> u64 {
+    plaintext_len + (plaintext_len / CHUNK_SIZE as u64 + 1) * Hmac::SIZE as u64
+}
+
+/// The inverse of `stream_body_len`.
+fn stream_plaintext_len(body_len: u64) -> io::Result<u64> {
+    let chunk = (Hmac::SIZE + CHUNK_SIZE) as u64;
+    let last = body_len % chunk;
+    if last < Hmac::SIZE as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "streamed message is truncated",
+        ));
+    }
+    Ok(body_len / chunk * CHUNK_SIZE as u64 + last - Hmac::SIZE as u64)
+}
+
+fn create(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+fn map(file: &File) -> io::Result<Mmap> {
+    // Safety: the mapping is only read from while it's alive, and the
+    // functions using it require that nothing else changes the file.
+    unsafe { Mmap::map(file) }
+}
+
+fn map_mut(file: &File) -> io::Result<MmapMut> {
+    // Safety: as for `map`.
+    unsafe { MmapMut::map_mut(file) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    fn decrypt_stream(cypher: &[u8], keypair: &Keypair) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        Decryptor::new(cypher, keypair)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    struct TempFile(PathBuf);
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let path =
+                std::env::temp_dir().join(format!("private-box-{}-{}", name, std::process::id()));
+            TempFile(path)
+        }
+    }
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let plain = TempFile::new("mmap-plain");
+        let boxed = TempFile::new("mmap-boxed");
+        let opened = TempFile::new("mmap-opened");
+
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, 3 * CHUNK_SIZE + 5] {
+            let msg: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            std::fs::write(&plain.0, &msg).unwrap();
+
+            let n = encrypt_file(&plain.0, &boxed.0, &[alice.public, bob.public]).unwrap();
+            let cypher = std::fs::read(&boxed.0).unwrap();
+            assert_eq!(n, cypher.len() as u64);
+            assert_eq!(decrypt_stream(&cypher, &bob).unwrap(), msg);
+
+            let n = decrypt_file(&boxed.0, &opened.0, &alice).unwrap();
+            assert_eq!(n, len as u64);
+            assert_eq!(std::fs::read(&opened.0).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn file_errors() {
+        let alice = Keypair::generate();
+        let plain = TempFile::new("mmap-err-plain");
+        let boxed = TempFile::new("mmap-err-boxed");
+        let opened = TempFile::new("mmap-err-opened");
+        std::fs::write(&plain.0, vec![7; CHUNK_SIZE + 10]).unwrap();
+
+        let err = encrypt_file(&plain.0, &boxed.0, &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        encrypt_file(&plain.0, &boxed.0, &[alice.public]).unwrap();
+        let err = decrypt_file(&boxed.0, &opened.0, &Keypair::generate()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!opened.0.exists());
+
+        // A corrupt final chunk is only found after the first is decrypted,
+        // but none of it is left in the output.
+        let mut cypher = std::fs::read(&boxed.0).unwrap();
+        *cypher.last_mut().unwrap() ^= 1;
+        std::fs::write(&boxed.0, &cypher).unwrap();
+        assert!(decrypt_file(&boxed.0, &opened.0, &alice).is_err());
+        assert_eq!(std::fs::read(&opened.0).unwrap(), b"");
+
+        std::fs::write(&boxed.0, &cypher[..cypher.len() - 20]).unwrap();
+        assert!(decrypt_file(&boxed.0, &opened.0, &alice).is_err());
+        assert_eq!(std::fs::read(&opened.0).unwrap(), b"");
+
+        assert!(encrypt_file(&opened.0.join("nope"), &boxed.0, &[alice.public]).is_err());
+    }
+}
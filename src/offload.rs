@@ -0,0 +1,101 @@
+use std::future::Future;
+use std::panic;
+
+use ssb_crypto::{Keypair, PublicKey};
+use tokio::task::JoinHandle;
+
+use crate::private_box::{decrypt_owned, encrypt_owned, EncryptError};
+
+/// Like `encrypt_owned`, but runs on tokio's blocking thread pool, so a large
+/// message doesn't hold up the async runtime while it's encrypted.
+///
+/// Must be called from within a tokio runtime, as `spawn_blocking` is. If the
+/// runtime shuts down before the work has started, awaiting the future panics,
+/// as there's no result to give.
+///
+/// # Example
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use private_box::{decrypt_async, encrypt_async, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let cypher = encrypt_async(b"hello!".to_vec(), vec![alice.public])
+///     .await
+///     .unwrap();
+/// assert_eq!(decrypt_async(cypher, alice).await.unwrap(), b"hello!");
+/// # }
+/// ```
+pub fn encrypt_async(
+    plaintext: Vec<u8>,
+    recipients: Vec<PublicKey>,
+) -> impl Future<Output = Result<Vec<u8>, EncryptError>> {
+    let task = tokio::task::spawn_blocking(move || encrypt_owned(plaintext, &recipients));
+    join(task)
+}
+
+/// Like `decrypt_owned`, but runs on tokio's blocking thread pool. See
+/// `encrypt_async`.
+pub fn decrypt_async(
+    cyphertext: Vec<u8>,
+    keypair: Keypair,
+) -> impl Future<Output = Option<Vec<u8>>> {
+    let task = tokio::task::spawn_blocking(move || decrypt_owned(cyphertext, &keypair));
+    join(task)
+}
+
+/// Waits for `task`. If it panicked, the panic carries on here; if it was
+/// cancelled, by its runtime shutting down before it ran, that's a panic too.
+async fn join<T>(task: JoinHandle<T>) -> T {
+    match task.await {
+        Ok(out) => out,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(_) => panic!("the tokio runtime shut down before the task ran"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn async_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let msg = vec![7; 1 << 20];
+
+        let cypher = encrypt_async(msg.clone(), vec![alice.public, bob.public])
+            .await
+            .unwrap();
+        assert_eq!(decrypt_async(cypher.clone(), bob).await.unwrap(), msg);
+        assert!(decrypt_async(cypher, Keypair::generate()).await.is_none());
+
+        assert_eq!(
+            encrypt_async(msg, vec![]).await,
+            Err(EncryptError::NoRecipients)
+        );
+    }
+
+    #[test]
+    fn cancelled_task_panics_with_a_message() {
+        use std::panic::AssertUnwindSafe;
+        use tokio::runtime::Builder;
+
+        // Blocking work spawned on a runtime that's already shut down is
+        // cancelled straight away.
+        let rt = Builder::new_current_thread().build().unwrap();
+        let handle = rt.handle().clone();
+        drop(rt);
+        let cypher = {
+            let _entered = handle.enter();
+            encrypt_async(vec![0; 10], vec![Keypair::generate().public])
+        };
+
+        let other = Builder::new_current_thread().build().unwrap();
+        let err = panic::catch_unwind(AssertUnwindSafe(|| other.block_on(cypher))).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<&str>(),
+            Some(&"the tokio runtime shut down before the task ran")
+        );
+    }
+}
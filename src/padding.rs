@@ -0,0 +1,145 @@
+//! Messages whose plaintext is padded, so their length says less about it.
+//!
+//! Before encrypting, the plaintext is prefixed with its length as a u64
+//! (big-endian), and zeros are added after it until the whole body is a
+//! multiple of `block_size` bytes. Like PKCS#7, the padding records how to
+//! remove itself, but as a length up front rather than in the fill bytes, so
+//! blocks can be bigger than 255 bytes. Every plaintext up to
+//! `block_size - 8` bytes long then makes a message of the same size.
+//!
+//! These are ordinary private-box messages, so `decrypt` opens them too, but
+//! returns the padded body; `decrypt_padded` strips the padding back off.
+use core::convert::{TryFrom, TryInto};
+use core::mem::size_of;
+
+use ssb_crypto::{Keypair, PublicKey};
+
+use crate::private_box::{decrypt, encrypt, EncryptError};
+
+const LEN_SIZE: usize = size_of::<u64>();
+
+/// The length of the body `encrypt_padded` seals for a `plaintext_len` byte
+/// plaintext: the length prefix and plaintext, rounded up to a whole number
+/// of blocks.
+///
+/// Panics if `block_size` is 0.
+pub const fn padded_len(plaintext_len: usize, block_size: usize) -> usize {
+    let len = LEN_SIZE + plaintext_len;
+    match len % block_size {
+        0 => len,
+        rem => len + (block_size - rem),
+    }
+}
+
+/// Like `encrypt`, but pads the plaintext to a multiple of `block_size` bytes
+/// first. Decrypt it with `decrypt_padded`.
+///
+/// Panics if `block_size` is 0.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_padded, encrypt_padded, Keypair};
+///
+/// let alice = Keypair::generate();
+/// let short = encrypt_padded(b"yes", &[alice.public], 256).unwrap();
+/// let long = encrypt_padded(b"no, not at all", &[alice.public], 256).unwrap();
+///
+/// assert_eq!(short.len(), long.len());
+/// assert_eq!(decrypt_padded(&short, &alice).unwrap(), b"yes");
+/// ```
+pub fn encrypt_padded(
+    plaintext: &[u8],
+    recipients: &[PublicKey],
+    block_size: usize,
+) -> Result<Vec<u8>, EncryptError> {
+    assert!(block_size > 0, "block_size must be at least 1");
+
+    let mut padded = vec![0; padded_len(plaintext.len(), block_size)];
+    padded[..LEN_SIZE].copy_from_slice(&(plaintext.len() as u64).to_be_bytes());
+    padded[LEN_SIZE..LEN_SIZE + plaintext.len()].copy_from_slice(plaintext);
+    encrypt(&padded, recipients)
+}
+
+/// Decrypt a message made by `encrypt_padded`, and remove the padding.
+/// Returns `None` if the message isn't addressed to `keypair`, or its
+/// body isn't padded.
+pub fn decrypt_padded(cyphertext: &[u8], keypair: &Keypair) -> Option<Vec<u8>> {
+    let mut body = decrypt(cyphertext, keypair)?;
+    if body.len() < LEN_SIZE {
+        return None;
+    }
+    let len = u64::from_be_bytes(body[..LEN_SIZE].try_into().ok()?);
+    let end = usize::try_from(len).ok()?.checked_add(LEN_SIZE)?;
+    if end > body.len() || body[end..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    body.truncate(end);
+    body.drain(..LEN_SIZE);
+    Some(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypted_len;
+
+    #[test]
+    fn padded_round_trip() {
+        let alice = Keypair::generate();
+        for n in [0, 1, 55, 56, 57, 100] {
+            let msg = vec![7; n];
+            let cypher = encrypt_padded(&msg, &[alice.public], 64).unwrap();
+            assert_eq!(decrypt_padded(&cypher, &alice).unwrap(), msg);
+            assert_eq!(cypher.len(), encrypted_len(padded_len(n, 64), 1));
+            assert!(decrypt_padded(&cypher, &Keypair::generate()).is_none());
+        }
+    }
+
+    #[test]
+    fn padded_sizes() {
+        assert_eq!(padded_len(0, 64), 64);
+        // Exactly filling a block doesn't add another.
+        assert_eq!(padded_len(56, 64), 64);
+        assert_eq!(padded_len(57, 64), 128);
+        assert_eq!(padded_len(0, 1), 8);
+        assert_eq!(padded_len(3, 1), 11);
+
+        let alice = Keypair::generate();
+        let sizes: Vec<usize> = (0..=56)
+            .map(|n| {
+                encrypt_padded(&vec![1; n], &[alice.public], 64)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert!(sizes.iter().all(|s| *s == sizes[0]));
+    }
+
+    #[test]
+    fn empty_plaintext() {
+        let alice = Keypair::generate();
+        let cypher = encrypt_padded(&[], &[alice.public], 16).unwrap();
+        assert_eq!(decrypt(&cypher, &alice).unwrap(), [0; 16]);
+        assert_eq!(decrypt_padded(&cypher, &alice).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn unpadded_bodies_are_rejected() {
+        let alice = Keypair::generate();
+        let mut too_long = 9u64.to_be_bytes().to_vec();
+        too_long.extend_from_slice(&[0; 8]);
+        let mut bad_fill = 1u64.to_be_bytes().to_vec();
+        bad_fill.extend_from_slice(&[5, 0, 1]);
+
+        for body in [&b"short"[..], &too_long, &bad_fill, &[0xff; 16]] {
+            let cypher = encrypt(body, &[alice.public]).unwrap();
+            assert!(decrypt_padded(&cypher, &alice).is_none());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_block_size_panics() {
+        let _ = encrypt_padded(b"hi", &[Keypair::generate().public], 0);
+    }
+}
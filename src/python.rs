@@ -0,0 +1,96 @@
+//! PyO3 bindings, so private-box can be used from Python.
+//!
+//! Keys are passed as `bytes`, in the same shape as the wasm bindings: a 32
+//! byte ed25519 public key, and a 64 byte secret key (seed then public key).
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use ssb_crypto::{Keypair, PublicKey};
+
+/// Encrypt `plaintext` to a list of recipient public keys.
+///
+/// Raises `ValueError` if any key isn't 32 bytes long, or if there are no
+/// recipients or too many of them.
+#[pyfunction]
+fn encrypt<'py>(
+    py: Python<'py>,
+    plaintext: &[u8],
+    recipients: Vec<Bound<'py, PyBytes>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let recipients = recipients
+        .iter()
+        .map(|k| PublicKey::from_slice(k.as_bytes()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| PyValueError::new_err("recipient keys must be 32 bytes"))?;
+
+    let cypher =
+        crate::encrypt(plaintext, &recipients).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(PyBytes::new(py, &cypher))
+}
+
+/// Decrypt `cyphertext` with a 64 byte secret key.
+///
+/// Returns `None` if the message isn't addressed to this key, and raises
+/// `ValueError` if the key is the wrong length.
+#[pyfunction]
+fn decrypt<'py>(
+    py: Python<'py>,
+    cyphertext: &[u8],
+    secret_key: &[u8],
+) -> PyResult<Option<Bound<'py, PyBytes>>> {
+    let keypair = Keypair::from_slice(secret_key)
+        .ok_or_else(|| PyValueError::new_err("secret key must be 64 bytes"))?;
+    Ok(crate::decrypt(cyphertext, &keypair).map(|p| PyBytes::new(py, &p)))
+}
+
+#[pymodule]
+fn private_box(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(encrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `python/test_private_box.py` against the module, with pytest if
+    /// it's installed, and otherwise by calling each `test_` function.
+    #[test]
+    fn python_tests() {
+        pyo3::append_to_inittab!(private_box);
+        pyo3::prepare_freethreaded_python();
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/python/test_private_box.py");
+        Python::with_gil(|py| {
+            let locals = pyo3::types::PyDict::new(py);
+            locals.set_item("path", path).unwrap();
+            py.run(
+                pyo3::ffi::c_str!(
+                    r#"
+import importlib.util
+try:
+    import pytest
+except ImportError:
+    pytest = None
+
+if pytest is not None:
+    assert pytest.main(["-q", "-p", "no:cacheprovider", path]) == 0
+else:
+    spec = importlib.util.spec_from_file_location("test_private_box", path)
+    tests = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(tests)
+    for name in dir(tests):
+        if name.startswith("test_"):
+            getattr(tests, name)()
+"#
+                ),
+                None,
+                Some(&locals),
+            )
+            .map_err(|e| e.display(py))
+            .unwrap();
+        });
+    }
+}
@@ -0,0 +1,140 @@
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use ssb_crypto::PublicKey;
+
+use crate::private_box::{EncryptError, PrivateBox, MAX_RECIPIENTS};
+
+/// A checked list of recipients, with no key listed twice.
+///
+/// Derefs to `[PublicKey]`, so it can be passed anywhere a slice of
+/// recipients is expected.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt, encrypt, Keypair, RecipientsBuilder};
+///
+/// let alice = Keypair::generate();
+/// let bob = Keypair::generate();
+///
+/// let recps = RecipientsBuilder::new()
+///     .add(alice.public)
+///     .add(bob.public)
+///     .add(alice.public)
+///     .build()
+///     .unwrap();
+/// assert_eq!(recps.len(), 2);
+///
+/// let cypher = encrypt(b"hello!", &recps).unwrap();
+/// assert_eq!(decrypt(&cypher, &bob).unwrap(), b"hello!");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipients(Vec<PublicKey>);
+
+impl Deref for Recipients {
+    type Target = [PublicKey];
+
+    fn deref(&self) -> &[PublicKey] {
+        &self.0
+    }
+}
+
+/// Collects recipients for a `Recipients`, dropping any key that's already
+/// been added.
+#[derive(Debug, Clone)]
+pub struct RecipientsBuilder {
+    keys: Vec<PublicKey>,
+    max_recipients: u8,
+}
+
+impl Default for RecipientsBuilder {
+    fn default() -> RecipientsBuilder {
+        RecipientsBuilder {
+            keys: Vec::new(),
+            max_recipients: MAX_RECIPIENTS as u8,
+        }
+    }
+}
+
+impl RecipientsBuilder {
+    pub fn new() -> RecipientsBuilder {
+        RecipientsBuilder::default()
+    }
+
+    /// The most distinct recipients `build` will accept. Defaults to 8;
+    /// see `PrivateBox::max_recipients`.
+    ///
+    /// Panics if `n` is 0.
+    pub fn max_recipients(mut self, n: u8) -> RecipientsBuilder {
+        assert!(n > 0, "max_recipients must be at least 1");
+        self.max_recipients = n;
+        self
+    }
+
+    /// Add a recipient, unless it's already been added.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, pk: PublicKey) -> RecipientsBuilder {
+        if !self.keys.contains(&pk) {
+            self.keys.push(pk);
+        }
+        self
+    }
+
+    /// Fails if no recipients were added, or more distinct ones than
+    /// `max_recipients`.
+    pub fn build(self) -> Result<Recipients, EncryptError> {
+        PrivateBox::new()
+            .max_recipients(self.max_recipients)
+            .check_recipients(self.keys.len())?;
+        Ok(Recipients(self.keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::{decrypt, encrypt, encrypted_len};
+    use ssb_crypto::Keypair;
+
+    #[test]
+    fn duplicate_recipients_get_one_slot() {
+        let alice = Keypair::generate();
+        let recps = RecipientsBuilder::new()
+            .add(alice.public)
+            .add(alice.public)
+            .add(alice.public)
+            .build()
+            .unwrap();
+        assert_eq!(&*recps, &[alice.public]);
+
+        let cypher = encrypt(&[0, 1, 2], &recps).unwrap();
+        assert_eq!(cypher.len(), encrypted_len(3, 1));
+        assert_eq!(decrypt(&cypher, &alice).unwrap(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn build_checks_the_count() {
+        assert_eq!(
+            RecipientsBuilder::new().build(),
+            Err(EncryptError::NoRecipients)
+        );
+
+        let keys: Vec<Keypair> = (0..9).map(|_| Keypair::generate()).collect();
+        let mut builder = RecipientsBuilder::new();
+        for k in &keys {
+            builder = builder.add(k.public).add(k.public);
+        }
+        assert_eq!(
+            builder.clone().build(),
+            Err(EncryptError::TooManyRecipients { got: 9, max: 8 })
+        );
+        assert_eq!(builder.max_recipients(9).build().unwrap().len(), 9);
+
+        // Eight distinct keys fit, however many times they're added.
+        let mut builder = RecipientsBuilder::new();
+        for k in keys.iter().take(8).cycle().take(30) {
+            builder = builder.add(k.public);
+        }
+        assert_eq!(builder.build().unwrap().len(), 8);
+    }
+}
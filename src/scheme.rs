@@ -0,0 +1,212 @@
+//! The cipher a message body is sealed with.
+//!
+//! Every private-box message starts the same way: a 24 byte nonce, the
+//! one-time public key, and key slots holding a 32 byte body key, sealed with
+//! `crypto_secretbox`. A `Scheme` decides what the body key and nonce are
+//! then used for: `ClassicScheme` seals the body with `crypto_secretbox` too,
+//! which is the format every other private-box implementation reads.
+//! `XChaChaScheme`, with the `xchacha` feature, seals it with
+//! XChaCha20-Poly1305 instead.
+//!
+//! The header leaves no room for other sizes, so a scheme's `NONCE_SIZE`,
+//! `KEY_SIZE` and `MAC_SIZE` must be 24, 32 and 16. That's checked when
+//! `encrypt_with_scheme` or `decrypt_with_scheme` is instantiated, so a
+//! scheme that gets them wrong fails to compile.
+use core::marker::PhantomData;
+
+#[cfg(feature = "xchacha")]
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+#[cfg(feature = "xchacha")]
+use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+
+use crate::private_box::BODY_KEY_SIZE;
+
+/// A way of sealing and opening message bodies.
+///
+/// A scheme with sizes that don't fit the header can't be used:
+/// ```compile_fail
+/// use private_box::{decrypt_with_scheme, Keypair, Scheme};
+///
+/// struct ShortNonce;
+/// impl Scheme for ShortNonce {
+///     const NONCE_SIZE: usize = 12;
+///     const KEY_SIZE: usize = 32;
+///     const MAC_SIZE: usize = 16;
+///     fn seal_body(_: &[u8], _: &[u8], _: &mut [u8], _: &mut [u8]) {}
+///     fn open_body(_: &[u8], _: &[u8], _: &mut [u8], _: &[u8]) -> bool { false }
+/// }
+///
+/// decrypt_with_scheme::<ShortNonce>(&[], &Keypair::generate());
+/// ```
+pub trait Scheme {
+    /// The length of the nonce the body is sealed with.
+    const NONCE_SIZE: usize;
+    /// The length of the body key.
+    const KEY_SIZE: usize;
+    /// The length of the authentication tag in front of the body.
+    const MAC_SIZE: usize;
+
+    /// Encrypt `body` in place, and write its tag to `mac`.
+    ///
+    /// `key`, `nonce` and `mac` are `KEY_SIZE`, `NONCE_SIZE` and `MAC_SIZE`
+    /// bytes long.
+    fn seal_body(key: &[u8], nonce: &[u8], body: &mut [u8], mac: &mut [u8]);
+
+    /// Decrypt `body` in place, if `mac` is its tag. Returns whether it was;
+    /// if not, `body` must be left as it was.
+    fn open_body(key: &[u8], nonce: &[u8], body: &mut [u8], mac: &[u8]) -> bool;
+}
+
+/// The private-box body format: `crypto_secretbox` (XSalsa20-Poly1305), as
+/// used by `encrypt` and `decrypt`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClassicScheme;
+
+impl Scheme for ClassicScheme {
+    const NONCE_SIZE: usize = Nonce::SIZE;
+    const KEY_SIZE: usize = BODY_KEY_SIZE;
+    const MAC_SIZE: usize = Hmac::SIZE;
+
+    fn seal_body(key: &[u8], nonce: &[u8], body: &mut [u8], mac: &mut [u8]) {
+        let hmac = secretbox_key(key).seal(body, &secretbox_nonce(nonce));
+        mac.copy_from_slice(&hmac.0);
+    }
+
+    fn open_body(key: &[u8], nonce: &[u8], body: &mut [u8], mac: &[u8]) -> bool {
+        match Hmac::from_slice(mac) {
+            Some(hmac) => secretbox_key(key).open(body, &hmac, &secretbox_nonce(nonce)),
+            None => false,
+        }
+    }
+}
+
+/// A body format for deployments that prefer XChaCha20-Poly1305 (libsodium's
+/// `crypto_aead_xchacha20poly1305_ietf`, with no additional data) to
+/// XSalsa20-Poly1305. The header and key slots are unchanged; the 16 byte
+/// Poly1305 tag goes where the secretbox mac would, in front of the body.
+///
+/// This is an extension to the private-box format. Other implementations,
+/// and `decrypt`, can't open these messages, and `decrypt_with_scheme` with
+/// this scheme can't open normal ones: the body just fails to authenticate.
+/// To read them elsewhere, open your key slot as usual, then open the body
+/// with `crypto_aead_xchacha20poly1305_ietf_decrypt_detached`, using the
+/// message's nonce, the body key from the slot, and empty additional data.
+/// They're the same as messages from `encrypt_with_aad` with an empty `aad`.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt, decrypt_with_scheme, encrypt_with_scheme, Keypair, XChaChaScheme};
+///
+/// let alice = Keypair::generate();
+/// let cypher = encrypt_with_scheme::<XChaChaScheme>(b"hello!", &[alice.public]).unwrap();
+/// assert_eq!(decrypt_with_scheme::<XChaChaScheme>(&cypher, &alice).unwrap(), b"hello!");
+/// assert!(decrypt(&cypher, &alice).is_none());
+/// ```
+#[cfg(feature = "xchacha")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XChaChaScheme;
+
+#[cfg(feature = "xchacha")]
+impl Scheme for XChaChaScheme {
+    const NONCE_SIZE: usize = Nonce::SIZE;
+    const KEY_SIZE: usize = BODY_KEY_SIZE;
+    const MAC_SIZE: usize = Hmac::SIZE;
+
+    fn seal_body(key: &[u8], nonce: &[u8], body: &mut [u8], mac: &mut [u8]) {
+        let tag = XChaCha20Poly1305::new_from_slice(key)
+            .expect("key is KEY_SIZE bytes")
+            .encrypt_in_place_detached(XNonce::from_slice(nonce), &[], body)
+            .expect("message is too long to seal");
+        mac.copy_from_slice(&tag);
+    }
+
+    fn open_body(key: &[u8], nonce: &[u8], body: &mut [u8], mac: &[u8]) -> bool {
+        // The tag is checked before anything is decrypted, so `body` is
+        // untouched if it's wrong.
+        XChaCha20Poly1305::new_from_slice(key)
+            .expect("key is KEY_SIZE bytes")
+            .decrypt_in_place_detached(XNonce::from_slice(nonce), &[], body, Tag::from_slice(mac))
+            .is_ok()
+    }
+}
+
+fn secretbox_key(key: &[u8]) -> Key {
+    let mut k = Key([0; 32]);
+    k.0.copy_from_slice(key);
+    k
+}
+
+fn secretbox_nonce(nonce: &[u8]) -> Nonce {
+    let mut n = Nonce([0; 24]);
+    n.0.copy_from_slice(nonce);
+    n
+}
+
+/// Fails to compile, when `OK` is used, unless `S` fits the message header.
+pub(crate) struct SchemeSizes<S>(PhantomData<S>);
+impl<S: Scheme> SchemeSizes<S> {
+    pub(crate) const OK: () = assert!(
+        S::NONCE_SIZE == Nonce::SIZE && S::KEY_SIZE == BODY_KEY_SIZE && S::MAC_SIZE == Hmac::SIZE,
+        "a scheme's nonce, key and mac must be 24, 32 and 16 bytes"
+    );
+}
+
+#[cfg(all(test, feature = "xchacha"))]
+mod tests {
+    use super::*;
+    use crate::private_box::{decrypt, decrypt_with_scheme, encrypt, encrypt_with_scheme};
+    use ssb_crypto::Keypair;
+
+    #[test]
+    fn xchacha_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let recps = [alice.public, bob.public];
+
+        for len in [0, 1, 100, 5000] {
+            let msg = vec![7; len];
+            let cypher = encrypt_with_scheme::<XChaChaScheme>(&msg, &recps).unwrap();
+            for k in [&alice, &bob] {
+                assert_eq!(
+                    decrypt_with_scheme::<XChaChaScheme>(&cypher, k).unwrap(),
+                    msg
+                );
+            }
+            assert!(decrypt_with_scheme::<XChaChaScheme>(&cypher, &Keypair::generate()).is_none());
+
+            // Flip a bit of the tag.
+            let mut tampered = cypher.clone();
+            tampered[cypher.len() - len - 16] ^= 1;
+            assert!(decrypt_with_scheme::<XChaChaScheme>(&tampered, &alice).is_none());
+        }
+    }
+
+    #[test]
+    fn xchacha_and_classic_dont_mix() {
+        let alice = Keypair::generate();
+        let classic = encrypt(b"hello!", &[alice.public]).unwrap();
+        let xchacha = encrypt_with_scheme::<XChaChaScheme>(b"hello!", &[alice.public]).unwrap();
+
+        assert!(decrypt_with_scheme::<XChaChaScheme>(&classic, &alice).is_none());
+        assert!(decrypt(&xchacha, &alice).is_none());
+        assert!(decrypt_with_scheme::<ClassicScheme>(&xchacha, &alice).is_none());
+    }
+
+    #[cfg(feature = "aad")]
+    #[test]
+    fn xchacha_is_aad_with_no_aad() {
+        let alice = Keypair::generate();
+        let cypher = encrypt_with_scheme::<XChaChaScheme>(b"hello!", &[alice.public]).unwrap();
+        assert_eq!(
+            crate::decrypt_with_aad(&cypher, &alice, b"").unwrap(),
+            b"hello!"
+        );
+
+        let cypher = crate::encrypt_with_aad(b"hello!", &[alice.public], b"").unwrap();
+        assert_eq!(
+            decrypt_with_scheme::<XChaChaScheme>(&cypher, &alice).unwrap(),
+            b"hello!"
+        );
+    }
+}
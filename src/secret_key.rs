@@ -0,0 +1,92 @@
+use core::fmt;
+
+use ssb_crypto::{Keypair, PublicKey};
+
+use crate::private_box::wipe;
+
+/// An ed25519 secret key: the 32 byte seed a `Keypair` is made from.
+///
+/// A distinct type from `PublicKey`, so one can't be passed where the other
+/// is expected. Its bytes are zeroed when it's dropped, and left out of its
+/// `Debug` output. `Keypair::secret` is ssb-crypto's own secret key type,
+/// whose `Debug` shows the key; `SecretKey::from(&keypair)` copies it into
+/// one of these.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt_with, encrypt_to, Keypair, SecretKey};
+///
+/// let alice = Keypair::generate();
+/// let secret = SecretKey::from_bytes(alice.secret.0);
+/// assert_eq!(secret.as_bytes(), &alice.secret.0);
+/// assert_eq!(secret.public_key(), alice.public);
+///
+/// let cypher = encrypt_to(b"hello!", &[alice.public]).unwrap();
+/// assert_eq!(decrypt_with(&cypher, &secret).unwrap(), b"hello!");
+/// ```
+pub struct SecretKey(pub(crate) [u8; 32]);
+
+impl SecretKey {
+    /// The secret key whose seed is `bytes`.
+    pub fn from_bytes(bytes: [u8; 32]) -> SecretKey {
+        SecretKey(bytes)
+    }
+
+    /// This key's 32 byte seed.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// The keypair this is the secret half of.
+    pub fn keypair(&self) -> Keypair {
+        Keypair::from_seed(&self.0).expect("any 32 bytes are an ed25519 seed")
+    }
+
+    /// The public half of this key's keypair.
+    pub fn public_key(&self) -> PublicKey {
+        self.keypair().public
+    }
+}
+
+impl From<&Keypair> for SecretKey {
+    fn from(keypair: &Keypair) -> SecretKey {
+        SecretKey(keypair.secret.0)
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        wipe(&mut self.0);
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretKey([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_key_round_trips() {
+        let alice = Keypair::generate();
+        let secret = SecretKey::from(&alice);
+        assert_eq!(secret.as_bytes(), &alice.secret.0);
+        assert_eq!(secret.keypair().public, alice.public);
+        assert_eq!(
+            SecretKey::from_bytes(*secret.as_bytes()).public_key(),
+            alice.public
+        );
+    }
+
+    #[test]
+    fn debug_redacts_the_key() {
+        let secret = SecretKey::from(&Keypair::generate());
+        let debug = format!("{:?}", secret);
+        assert_eq!(debug, "SecretKey([REDACTED])");
+        assert!(!debug.contains(&format!("{:?}", secret.0)));
+    }
+}
@@ -0,0 +1,185 @@
+use rand_core::OsRng;
+use ssb_crypto::secretbox::Nonce;
+use ssb_crypto::PublicKey;
+
+use crate::private_box::{encrypted_len, EncryptError, PrivateBox, Sealer};
+
+/// Encrypts many messages to the same recipients, reusing one one-time
+/// keypair for all of them.
+///
+/// Most of the cost of `encrypt` is deriving a secret shared with each
+/// recipient, which depends on the message's one-time keypair. A
+/// `SessionEncryptor` derives those once, in `new`; each message after that
+/// only needs a new nonce and body key, and a secretbox per recipient.
+///
+/// As every message's key slots are sealed with the same shared secrets, a
+/// nonce must never be used twice. Instead of drawing them at random, the
+/// session picks a random starting nonce and counts up from it, one per
+/// message, and returns `EncryptError::SessionExhausted` rather than wrap
+/// around (after 2^64 messages).
+///
+/// # Security
+///
+/// This gives up some of what a fresh one-time keypair per message buys:
+///
+/// - Every message in a session has the same one-time public key, so anyone
+///   can tell they were sent together, to the same recipients.
+/// - The shared secrets are kept for the life of the session. Anyone who gets
+///   hold of them can read every message of the session, rather than just
+///   one. They're zeroed when the `SessionEncryptor` is dropped, so keep
+///   sessions short.
+/// - There's no forward secrecy between the messages of a session: the
+///   shared secrets are only forgotten when the session is, so compromising
+///   the sender during a session exposes the messages already sent in it
+///   too.
+///
+/// In exchange, the per-message cost no longer grows with a scalar
+/// multiplication per recipient (see the `session` benchmark); for small
+/// messages to several recipients that's most of the time `encrypt` takes.
+///
+/// The messages themselves are ordinary private-box messages, and are
+/// decrypted with `decrypt` as usual.
+///
+/// # Example
+/// ```
+/// use private_box::{decrypt, Keypair, SessionEncryptor};
+///
+/// let alice = Keypair::generate();
+/// let mut session = SessionEncryptor::new(&[alice.public]).unwrap();
+///
+/// for msg in [&b"one"[..], &b"two"[..]].iter() {
+///     let cypher = session.encrypt(msg).unwrap();
+///     assert_eq!(&decrypt(&cypher, &alice).unwrap(), msg);
+/// }
+/// ```
+pub struct SessionEncryptor {
+    sealer: Sealer,
+    /// The nonce of the first message. The last 8 bytes, as a big-endian
+    /// u64, are the counter.
+    first_nonce: Nonce,
+    /// The number of nonces used so far.
+    sent: u64,
+}
+
+impl SessionEncryptor {
+    /// Start a session encrypting to `recipients`. Fails for the same
+    /// reasons `encrypt` does.
+    pub fn new(recipients: &[PublicKey]) -> Result<SessionEncryptor, EncryptError> {
+        let pbox = PrivateBox::new();
+        pbox.check_recipients(recipients.len())?;
+        Ok(SessionEncryptor {
+            sealer: pbox.sealer(recipients, &mut OsRng)?,
+            first_nonce: Nonce::generate_with_rng(&mut OsRng),
+            sent: 0,
+        })
+    }
+
+    /// Encrypt a message to the session's recipients.
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptError> {
+        PrivateBox::check_plaintext_len(plaintext.len())?;
+        let nonce = self.next_nonce()?;
+        let sealer = self.sealer.renew(nonce, &mut OsRng);
+        let mut out = vec![0; encrypted_len(plaintext.len(), sealer.recipient_count())];
+        sealer.seal(plaintext, &mut out);
+        Ok(out)
+    }
+
+    /// The nonce for the next message. Adding the count to the last 8 bytes
+    /// (wrapping within them) gives a different nonce for every count.
+    fn next_nonce(&mut self) -> Result<Nonce, EncryptError> {
+        if self.sent == u64::MAX {
+            return Err(EncryptError::SessionExhausted);
+        }
+        let mut nonce = Nonce(self.first_nonce.0);
+        let (_, low) = nonce.0.split_at_mut(16);
+        let start = u64::from_be_bytes([
+            low[0], low[1], low[2], low[3], low[4], low[5], low[6], low[7],
+        ]);
+        low.copy_from_slice(&start.wrapping_add(self.sent).to_be_bytes());
+        self.sent += 1;
+        Ok(nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::decrypt;
+    use ssb_crypto::Keypair;
+    use std::collections::HashSet;
+
+    #[test]
+    fn session_messages_decrypt() {
+        let keys: Vec<Keypair> = (0..3).map(|_| Keypair::generate()).collect();
+        let recps: Vec<PublicKey> = keys.iter().map(|k| k.public).collect();
+        let mut session = SessionEncryptor::new(&recps).unwrap();
+
+        let messages: Vec<Vec<u8>> = (0..20).map(|i| vec![i; i as usize * 10]).collect();
+        let cyphers: Vec<Vec<u8>> = messages
+            .iter()
+            .map(|m| session.encrypt(m).unwrap())
+            .collect();
+
+        for (msg, cypher) in messages.iter().zip(&cyphers) {
+            assert_eq!(cypher.len(), encrypted_len(msg.len(), 3));
+            // Same one-time public key every time, but never the same nonce.
+            assert_eq!(cypher[24..56], cyphers[0][24..56]);
+            for other in cyphers.iter().filter(|c| *c != cypher) {
+                assert_ne!(cypher[..24], other[..24]);
+            }
+            for k in &keys {
+                assert_eq!(&decrypt(cypher, k).unwrap(), msg);
+            }
+        }
+
+        assert!(decrypt(&cyphers[0], &Keypair::generate()).is_none());
+    }
+
+    #[test]
+    fn session_nonces_are_unique() {
+        let alice = Keypair::generate();
+        let mut session = SessionEncryptor::new(&[alice.public]).unwrap();
+        // Start just short of where the counter bytes wrap, to check that
+        // wrapping doesn't repeat a nonce.
+        session.first_nonce.0[16..].copy_from_slice(&(u64::MAX - 1000).to_be_bytes());
+
+        let mut seen = HashSet::new();
+        for _ in 0..1 << 20 {
+            assert!(seen.insert(session.next_nonce().unwrap().0));
+        }
+        // The first 16 bytes never change.
+        assert!(seen.iter().all(|n| n[..16] == session.first_nonce.0[..16]));
+
+        let cypher = session.encrypt(b"hi").unwrap();
+        assert!(!seen.contains(&cypher[..24]));
+        assert_eq!(decrypt(&cypher, &alice).unwrap(), b"hi");
+    }
+
+    #[test]
+    fn session_exhaustion_errors() {
+        let alice = Keypair::generate();
+        let mut session = SessionEncryptor::new(&[alice.public]).unwrap();
+        session.sent = u64::MAX - 1;
+        assert!(session.encrypt(b"last").is_ok());
+        assert_eq!(
+            session.encrypt(b"one more"),
+            Err(EncryptError::SessionExhausted)
+        );
+        assert_eq!(
+            session.encrypt(b"one more"),
+            Err(EncryptError::SessionExhausted)
+        );
+    }
+
+    #[test]
+    fn session_recipients_are_checked() {
+        assert_eq!(
+            SessionEncryptor::new(&[]).err(),
+            Some(EncryptError::NoRecipients)
+        );
+        assert_eq!(
+            SessionEncryptor::new(&[PublicKey([0; 32])]).err(),
+            Some(EncryptError::InvalidRecipientKey(0))
+        );
+    }
+}
@@ -0,0 +1,632 @@
+//! Encrypt and decrypt large messages a chunk at a time.
+//!
+//! A streamed message starts with the same header as a normal private-box
+//! message (nonce, one-time public key and a key slot for each recipient),
+//! but its body is split into chunks that are each sealed separately:
+//!
+//! ```text
+//! nonce (24) | one-time public key (32) | key slots (49 * recipients) | chunk | chunk | ...
+//!
+//! chunk = mac (16) | encrypted plaintext (CHUNK_SIZE bytes, or fewer for the final chunk)
+//! ```
+//!
+//! Chunk `i` (counting from zero) is sealed with the body key and the header
+//! nonce plus `i + 1`, treating the whole 24 byte nonce as a little-endian
+//! number. `i` is a u64, so every chunk's nonce is different, and a stream
+//! can have up to `MAX_CHUNKS` chunks (over a zettabyte); writing or reading
+//! any more is an error rather than a reused nonce.
+//! Every full-size chunk is followed by another chunk, and the final chunk
+//! is always shorter than `CHUNK_SIZE` (it may be empty), so a stream that
+//! has been cut short can be detected.
+//!
+//! This is an extension of the private-box format: a streamed message can
+//! only be opened with a `Decryptor`, not with `private_box::decrypt`, and
+//! other private-box implementations don't understand it.
+use std::io::{self, Read, Write};
+
+use rand_core::OsRng;
+use ssb_crypto::ephemeral::EphPublicKey;
+use ssb_crypto::secretbox::{Hmac, Key, Nonce};
+use ssb_crypto::{Keypair, PublicKey};
+use zerocopy::AsBytes;
+
+use crate::private_box::{
+    ensure_init, header_len, slot_key, EncryptError, MsgKey, PrivateBox, HEADER_SIZE, SLOT_SIZE,
+};
+
+/// The amount of plaintext in each full chunk of a streamed message.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The most chunks a streamed message can have.
+pub const MAX_CHUNKS: u64 = u64::MAX;
+
+/// Encrypts a stream of plaintext written to it, writing the encrypted
+/// message to `inner`.
+///
+/// `finalize` must be called once all the plaintext has been written,
+/// otherwise the message will be incomplete and can't be decrypted.
+///
+/// # Example
+/// ```
+/// use private_box::stream::Encryptor;
+/// use private_box::Keypair;
+/// use std::io::Write;
+///
+/// let alice = Keypair::generate();
+///
+/// let mut cypher = vec![];
+/// let mut enc = Encryptor::new(&mut cypher, &[alice.public]).unwrap();
+/// enc.write_all(b"hello!").unwrap();
+/// let len = enc.finalize().unwrap();
+/// assert_eq!(len, cypher.len() as u64);
+/// ```
+pub struct Encryptor<W: Write> {
+    inner: W,
+    key: Key,
+    nonce: Nonce,
+    chunk_index: u64,
+    // The message header, until it's been written to `inner`.
+    header: Vec<u8>,
+    chunk: Vec<u8>,
+    written: u64,
+}
+
+impl<W: Write> Encryptor<W> {
+    /// Start a message to `recipients`. Nothing is written to `inner`
+    /// until the first chunk is ready (or `finalize` is called).
+    pub fn new(inner: W, recipients: &[PublicKey]) -> Result<Encryptor<W>, EncryptError> {
+        Encryptor::with_box(inner, recipients, PrivateBox::new())
+    }
+
+    /// Like `new`, but with `pbox`'s limit on the number of recipients.
+    pub fn with_box(
+        inner: W,
+        recipients: &[PublicKey],
+        pbox: PrivateBox,
+    ) -> Result<Encryptor<W>, EncryptError> {
+        pbox.check_recipients(recipients.len())?;
+
+        let mut header = vec![0; header_len(recipients.len())];
+        let (nonce, key, _) = pbox
+            .sealer(recipients, &mut OsRng)?
+            .write_header(&mut header);
+
+        Ok(Encryptor {
+            inner,
+            key,
+            nonce,
+            chunk_index: 0,
+            header,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            written: 0,
+        })
+    }
+
+    /// Seal and write the final chunk, and flush `inner`.
+    /// Returns the total number of bytes written to `inner`.
+    pub fn finalize(mut self) -> io::Result<u64> {
+        if self.chunk.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        self.write_chunk()?;
+        self.inner.flush()?;
+        Ok(self.written)
+    }
+
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if !self.header.is_empty() {
+            self.inner.write_all(&self.header)?;
+            self.written += self.header.len() as u64;
+            self.header = Vec::new();
+        }
+
+        if self.chunk_index == MAX_CHUNKS {
+            return Err(too_many_chunks());
+        }
+        let nonce = chunk_nonce(&self.nonce, self.chunk_index);
+        let hmac = self.key.seal(&mut self.chunk, &nonce);
+        self.inner.write_all(hmac.as_bytes())?;
+        self.inner.write_all(&self.chunk)?;
+
+        self.written += (hmac.as_bytes().len() + self.chunk.len()) as u64;
+        self.chunk_index += 1;
+        self.chunk.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full chunk is only written once there's more plaintext,
+        // because the final chunk must be shorter than CHUNK_SIZE.
+        if self.chunk.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        let n = buf.len().min(CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Flushes `inner`. Buffered plaintext is not written until a
+    /// chunk is full, or `finalize` is called.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a streamed message read from `inner`.
+///
+/// # Example
+/// ```
+/// use private_box::stream::{Decryptor, Encryptor};
+/// use private_box::Keypair;
+/// use std::io::{Read, Write};
+///
+/// let alice = Keypair::generate();
+///
+/// let mut cypher = vec![];
+/// let mut enc = Encryptor::new(&mut cypher, &[alice.public]).unwrap();
+/// enc.write_all(b"hello!").unwrap();
+/// enc.finalize().unwrap();
+///
+/// let mut dec = Decryptor::new(&cypher[..], &alice).unwrap();
+/// let mut plaintext = vec![];
+/// dec.read_to_end(&mut plaintext).unwrap();
+/// assert_eq!(plaintext, b"hello!");
+/// ```
+pub struct Decryptor<R: Read> {
+    inner: R,
+    key: Key,
+    nonce: Nonce,
+    chunk_index: u64,
+    // The current chunk, mac and all; `pos` is the start of the
+    // plaintext that hasn't been read yet.
+    chunk: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Decryptor<R> {
+    /// Read the message header from `inner` and recover the body key.
+    ///
+    /// Fails with `ErrorKind::InvalidData` if the message isn't addressed
+    /// to `keypair`, before any of the body is read.
+    pub fn new(inner: R, keypair: &Keypair) -> io::Result<Decryptor<R>> {
+        Decryptor::with_box(inner, keypair, PrivateBox::new())
+    }
+
+    /// Like `new`, but trying only as many key slots as `pbox`'s
+    /// `max_slots`, rather than up to 255.
+    pub fn with_box(mut inner: R, keypair: &Keypair, pbox: PrivateBox) -> io::Result<Decryptor<R>> {
+        ensure_init();
+        let mut prefix = [0; HEADER_SIZE];
+        inner.read_exact(&mut prefix)?;
+        let nonce = Nonce::from_slice(&prefix[..24]).unwrap();
+        let eph_pk = EphPublicKey::from_slice(&prefix[24..]).unwrap();
+
+        let key_key = slot_key(keypair, &eph_pk).ok_or_else(not_a_recipient)?;
+
+        // The number of slots isn't known until one has been opened,
+        // so they're read one at a time.
+        let mut msg_key = MsgKey::zeroed();
+        let mut slot = [0; SLOT_SIZE];
+        let mut opened = None;
+        for i in 0..pbox.slot_limit() {
+            if read_full(&mut inner, &mut slot)? < slot.len() {
+                break;
+            }
+            if key_key.open_attached_into(&slot, &nonce, msg_key.as_bytes_mut()) {
+                opened = Some(i);
+                break;
+            }
+        }
+        let opened = opened.ok_or_else(not_a_recipient)?;
+
+        let remaining = (msg_key.recp_count as usize)
+            .checked_sub(opened + 1)
+            .ok_or_else(|| invalid_data("key slot has an invalid recipient count"))?;
+        for _ in 0..remaining {
+            inner.read_exact(&mut slot)?;
+        }
+
+        let MsgKey { key, .. } = msg_key;
+        Ok(Decryptor {
+            inner,
+            key,
+            nonce,
+            chunk_index: 0,
+            chunk: Vec::with_capacity(Hmac::SIZE + CHUNK_SIZE),
+            pos: 0,
+            done: false,
+        })
+    }
+
+    /// The reader the message is being read from. After `new`, it's at the
+    /// start of the body.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        if self.chunk_index == MAX_CHUNKS {
+            return Err(too_many_chunks());
+        }
+        self.chunk.resize(Hmac::SIZE + CHUNK_SIZE, 0);
+        let n = read_full(&mut self.inner, &mut self.chunk)?;
+        self.chunk.truncate(n);
+
+        // Only the final chunk is shorter than a full one.
+        if n < Hmac::SIZE + CHUNK_SIZE {
+            if n < Hmac::SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "streamed message is truncated",
+                ));
+            }
+            self.done = true;
+        }
+
+        let nonce = chunk_nonce(&self.nonce, self.chunk_index);
+        let (hmac, text) = self.chunk.split_at_mut(Hmac::SIZE);
+        let hmac = Hmac::from_slice(hmac).unwrap();
+        if !self.key.open(text, &hmac, &nonce) {
+            return Err(invalid_data(
+                "streamed message chunk failed to authenticate",
+            ));
+        }
+        self.chunk_index += 1;
+        self.pos = Hmac::SIZE;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Like `read_exact`, but stops at the end of the stream, returning the
+/// number of bytes read.
+fn read_full<R: Read>(r: &mut R, mut buf: &mut [u8]) -> io::Result<usize> {
+    let len = buf.len();
+    while !buf.is_empty() {
+        match r.read(buf) {
+            Ok(0) => break,
+            Ok(n) => buf = &mut buf[n..],
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len - buf.len())
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn not_a_recipient() -> io::Error {
+    invalid_data("not a recipient of this message")
+}
+
+// `io::Error::other` would need Rust 1.74.
+#[allow(clippy::io_other_error)]
+fn too_many_chunks() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "streamed message has run out of chunk nonces",
+    )
+}
+
+/// The nonce for chunk `index`: `base + index + 1`, as a little-endian number.
+pub(crate) fn chunk_nonce(base: &Nonce, index: u64) -> Nonce {
+    let mut nonce = *base;
+    let mut carry = index as u128 + 1;
+    for b in nonce.0.iter_mut() {
+        carry += *b as u128;
+        *b = carry as u8;
+        carry >>= 8;
+    }
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private_box::decrypt_key;
+    use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+    use std::thread;
+
+    // An in-memory pipe: what's written arrives at the reader in the same
+    // pieces, and the reader sees the end once the writer is dropped.
+    fn pipe() -> (PipeReader, PipeWriter) {
+        let (tx, rx) = sync_channel(4);
+        (
+            PipeReader {
+                rx,
+                buf: vec![],
+                pos: 0,
+            },
+            PipeWriter(tx),
+        )
+    }
+
+    struct PipeWriter(SyncSender<Vec<u8>>);
+
+    impl Write for PipeWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .send(buf.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct PipeReader {
+        rx: Receiver<Vec<u8>>,
+        buf: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            while self.pos == self.buf.len() {
+                match self.rx.recv() {
+                    Ok(buf) => {
+                        self.buf = buf;
+                        self.pos = 0;
+                    }
+                    Err(_) => return Ok(0),
+                }
+            }
+            let n = out.len().min(self.buf.len() - self.pos);
+            out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    // Opens the chunks of a streamed message by hand.
+    fn open_chunks(cypher: &[u8], keypair: &Keypair, recipients: usize) -> Option<Vec<u8>> {
+        let key = decrypt_key(cypher, keypair)?;
+        let nonce = Nonce::from_slice(&cypher[..24])?;
+        let key = Key::from_slice(&key.as_array()[1..])?;
+
+        let mut out = vec![];
+        let chunks = cypher[header_len(recipients)..].chunks(Hmac::SIZE + CHUNK_SIZE);
+        for (i, chunk) in chunks.enumerate() {
+            let mut text = vec![0; chunk.len() - Hmac::SIZE];
+            if !key.open_attached_into(chunk, &chunk_nonce(&nonce, i as u64), &mut text) {
+                return None;
+            }
+            out.extend_from_slice(&text);
+        }
+        Some(out)
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let plaintext: Vec<u8> = (0..CHUNK_SIZE * 2 + 100).map(|i| i as u8).collect();
+
+        let mut cypher = vec![];
+        let mut enc = Encryptor::new(&mut cypher, &[alice.public, bob.public]).unwrap();
+        // Uneven writes, to cross chunk boundaries.
+        for part in plaintext.chunks(1000) {
+            enc.write_all(part).unwrap();
+        }
+        let written = enc.finalize().unwrap();
+
+        assert_eq!(written, cypher.len() as u64);
+        assert_eq!(
+            cypher.len(),
+            header_len(2) + plaintext.len() + 3 * Hmac::SIZE
+        );
+        assert_eq!(open_chunks(&cypher, &alice, 2).unwrap(), plaintext);
+        assert_eq!(open_chunks(&cypher, &bob, 2).unwrap(), plaintext);
+        assert!(open_chunks(&cypher, &Keypair::generate(), 2).is_none());
+    }
+
+    #[test]
+    fn exact_multiple_ends_with_empty_chunk() {
+        let alice = Keypair::generate();
+
+        let mut cypher = vec![];
+        let mut enc = Encryptor::new(&mut cypher, &[alice.public]).unwrap();
+        enc.write_all(&[7; CHUNK_SIZE]).unwrap();
+        enc.finalize().unwrap();
+
+        assert_eq!(cypher.len(), header_len(1) + CHUNK_SIZE + 2 * Hmac::SIZE);
+        assert_eq!(
+            open_chunks(&cypher, &alice, 1).unwrap(),
+            vec![7; CHUNK_SIZE]
+        );
+    }
+
+    #[test]
+    fn empty_stream() {
+        let alice = Keypair::generate();
+
+        let mut cypher = vec![];
+        let enc = Encryptor::new(&mut cypher, &[alice.public]).unwrap();
+        assert_eq!(enc.finalize().unwrap(), (header_len(1) + Hmac::SIZE) as u64);
+        assert_eq!(open_chunks(&cypher, &alice, 1).unwrap(), b"");
+    }
+
+    #[test]
+    fn recipients_are_checked() {
+        let mut cypher = vec![];
+        assert_eq!(
+            Encryptor::new(&mut cypher, &[]).err(),
+            Some(EncryptError::NoRecipients)
+        );
+        assert_eq!(
+            Encryptor::new(&mut cypher, &[PublicKey([0; 32])]).err(),
+            Some(EncryptError::InvalidRecipientKey(0))
+        );
+    }
+
+    fn encrypt_stream(plaintext: &[u8], recipients: &[PublicKey]) -> Vec<u8> {
+        let mut cypher = vec![];
+        let mut enc = Encryptor::new(&mut cypher, recipients).unwrap();
+        enc.write_all(plaintext).unwrap();
+        enc.finalize().unwrap();
+        cypher
+    }
+
+    fn decrypt_stream(cypher: &[u8], keypair: &Keypair) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        Decryptor::new(cypher, keypair)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decryptor_round_trips_through_a_pipe() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+        let recps = [alice.public, bob.public];
+        let plaintext: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+        let (reader, writer) = pipe();
+        let input = plaintext.clone();
+        let t = thread::spawn(move || {
+            let mut enc = Encryptor::new(writer, &recps).unwrap();
+            enc.write_all(&input).unwrap();
+            enc.finalize().unwrap()
+        });
+
+        let mut out = vec![];
+        Decryptor::new(reader, &bob)
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        let written = t.join().unwrap();
+
+        assert_eq!(out, plaintext);
+        assert_eq!(
+            written,
+            (header_len(2) + plaintext.len() + (plaintext.len() / CHUNK_SIZE + 1) * Hmac::SIZE)
+                as u64
+        );
+    }
+
+    #[test]
+    fn decryptor_edge_lengths() {
+        let alice = Keypair::generate();
+        for len in &[
+            0,
+            1,
+            CHUNK_SIZE - 1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + 1,
+            2 * CHUNK_SIZE,
+        ] {
+            let plaintext = vec![3; *len];
+            let cypher = encrypt_stream(&plaintext, &[alice.public]);
+            assert_eq!(decrypt_stream(&cypher, &alice).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn decryptor_not_a_recipient() {
+        let alice = Keypair::generate();
+        let cypher = encrypt_stream(b"hello", &[alice.public]);
+
+        let err = Decryptor::new(&cypher[..], &Keypair::generate())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decryptor_detects_truncation_and_tampering() {
+        let alice = Keypair::generate();
+        let plaintext = vec![5; CHUNK_SIZE * 2];
+        let cypher = encrypt_stream(&plaintext, &[alice.public]);
+
+        // Cut off the final (empty) chunk.
+        let cut = cypher.len() - Hmac::SIZE;
+        assert!(decrypt_stream(&cypher[..cut], &alice).is_err());
+        // Cut in the middle of a chunk.
+        assert!(decrypt_stream(&cypher[..cypher.len() - 100], &alice).is_err());
+
+        let mut tampered = cypher.clone();
+        tampered[header_len(1) + 20] ^= 1;
+        assert!(decrypt_stream(&tampered, &alice).is_err());
+    }
+
+    #[test]
+    fn chunk_limit_is_an_error() {
+        let alice = Keypair::generate();
+        let mut cypher = vec![];
+        let mut enc = Encryptor::new(&mut cypher, &[alice.public]).unwrap();
+        enc.chunk_index = MAX_CHUNKS - 2;
+        // Each full chunk is written once the next is started, so these
+        // use the last two chunk indices, leaving none for the final chunk.
+        enc.write_all(&vec![1; 2 * CHUNK_SIZE + 1]).unwrap();
+        let err = enc.finalize().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "streamed message has run out of chunk nonces"
+        );
+
+        // The index carries into the rest of the nonce, rather than wrapping
+        // within the low 8 bytes.
+        let mut base = Nonce([0; 24]);
+        base.0[..8].copy_from_slice(&[0xff; 8]);
+        let last = chunk_nonce(&base, MAX_CHUNKS - 1);
+        assert_eq!(
+            last.0[..8],
+            [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(last.0[8], 1);
+
+        let mut dec = Decryptor::new(&cypher[..], &alice).unwrap();
+        dec.chunk_index = MAX_CHUNKS;
+        let err = dec.read(&mut [0; 10]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "streamed message has run out of chunk nonces"
+        );
+    }
+
+    #[test]
+    fn chunk_nonce_carries() {
+        let base = Nonce([0xff; 24]);
+        assert_eq!(chunk_nonce(&base, 0).0, [0; 24]);
+
+        let mut base = Nonce([0; 24]);
+        base.0[0] = 0xfe;
+        let n = chunk_nonce(&base, 1);
+        assert_eq!(n.0[0], 0);
+        assert_eq!(n.0[1], 1);
+        assert_eq!(n.0[2..], [0; 22]);
+    }
+
+    #[test]
+    fn chunk_nonce_is_little_endian() {
+        // Fixed bytes, so a host-order conversion would fail on one of a
+        // big- or little-endian target.
+        let n = chunk_nonce(&Nonce([0; 24]), 0x0102_0304_0506);
+        assert_eq!(n.0[..7], [0x07, 0x05, 0x04, 0x03, 0x02, 0x01, 0]);
+        assert_eq!(n.0[7..], [0; 17]);
+
+        let mut base = Nonce([0; 24]);
+        base.0[23] = 0x80;
+        assert_eq!(chunk_nonce(&base, u64::MAX).0[8], 1);
+        assert_eq!(chunk_nonce(&base, u64::MAX).0[23], 0x80);
+    }
+}
@@ -0,0 +1,80 @@
+//! `wasm-bindgen` exports, for running private-box in the browser.
+//!
+//! Keys are passed in the same shape ssb-keys uses once decoded from base64:
+//! a 32 byte ed25519 public key, and a 64 byte secret key (seed then public key).
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use ssb_crypto::{Keypair, PublicKey};
+
+/// Encrypt `plaintext` to an array of recipient public keys (`Uint8Array`s).
+///
+/// Throws if any key isn't 32 bytes long, or if there are no recipients or
+/// too many of them.
+#[wasm_bindgen]
+pub fn encrypt(plaintext: &[u8], recipients: Array) -> Result<Vec<u8>, JsValue> {
+    let recipients = recipients
+        .iter()
+        .map(|k| PublicKey::from_slice(&Uint8Array::new(&k).to_vec()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| JsValue::from_str("recipient keys must be 32 bytes"))?;
+
+    crate::encrypt(plaintext, &recipients).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Decrypt `cyphertext` with a 64 byte secret key.
+///
+/// Returns `undefined` if the message isn't addressed to this key, and
+/// throws if the key is the wrong length.
+#[wasm_bindgen]
+pub fn decrypt(cyphertext: &[u8], secret_key: &[u8]) -> Result<Option<Vec<u8>>, JsValue> {
+    let keypair = Keypair::from_slice(secret_key)
+        .ok_or_else(|| JsValue::from_str("secret key must be 64 bytes"))?;
+    Ok(crate::decrypt(cyphertext, &keypair))
+}
+
+// Run with `wasm-pack test --headless --firefox -- --features wasm`.
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn secret_bytes(keypair: &Keypair) -> Vec<u8> {
+        let mut bytes = keypair.secret.0.to_vec();
+        bytes.extend_from_slice(&keypair.public.0);
+        bytes
+    }
+
+    #[wasm_bindgen_test]
+    fn round_trip() {
+        let alice = Keypair::generate();
+        let bob = Keypair::generate();
+
+        let recipients = Array::of2(
+            &Uint8Array::from(&alice.public.0[..]),
+            &Uint8Array::from(&bob.public.0[..]),
+        );
+        let cypher = encrypt(b"hello from wasm", recipients).unwrap();
+
+        assert_eq!(
+            decrypt(&cypher, &secret_bytes(&alice)).unwrap().unwrap(),
+            b"hello from wasm"
+        );
+        assert_eq!(
+            decrypt(&cypher, &secret_bytes(&bob)).unwrap().unwrap(),
+            b"hello from wasm"
+        );
+
+        let eve = Keypair::generate();
+        assert_eq!(decrypt(&cypher, &secret_bytes(&eve)).unwrap(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn bad_keys_throw() {
+        let short = Array::of1(&Uint8Array::from(&[0u8; 31][..]));
+        assert!(encrypt(b"hi", short).is_err());
+        assert!(decrypt(&[0; 200], &[0; 32]).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+// Encrypts or decrypts one message with the js private-box, for the rust
+// crate's `node-interop` tests. Reads a request from stdin, one of
+//
+//   {"encrypt": {"msg": <base64>, "recps": [<base64 ed25519 public key>]}}
+//   {"decrypt": {"cypher": <base64>, "secret": <base64 ed25519 secret key>}}
+//
+// and writes the base64 result to stdout: the message, or for a decrypt,
+// the plaintext, or nothing if the key can't open it. Exits with 2 if the
+// packages haven't been installed, so the tests can be skipped.
+let pbox, sodium
+try {
+  pbox = require('private-box')
+  sodium = require('chloride')
+} catch (e) {
+  console.error(e.message)
+  process.exit(2)
+}
+
+const b64 = s => Buffer.from(s, 'base64')
+
+let input = ''
+process.stdin.on('data', d => { input += d })
+process.stdin.on('end', () => {
+  const req = JSON.parse(input)
+  if (req.encrypt) {
+    const recps = req.encrypt.recps.map(pk =>
+      sodium.crypto_sign_ed25519_pk_to_curve25519(b64(pk)))
+    process.stdout.write(pbox.encrypt(b64(req.encrypt.msg), recps).toString('base64'))
+  } else {
+    const sk = sodium.crypto_sign_ed25519_sk_to_curve25519(b64(req.decrypt.secret))
+    const msg = pbox.decrypt(b64(req.decrypt.cypher), sk)
+    if (msg) process.stdout.write(msg.toString('base64'))
+  }
+})
@@ -0,0 +1,52 @@
+//! Counts the allocations `encrypt` and `decrypt` make. Each should make
+//! exactly one, for the `Vec` it returns, however many recipients there are:
+//! the header and key slots are written straight into the output, not built
+//! up in buffers of their own.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use private_box::{decrypt, encrypt, Keypair, PublicKey};
+
+struct Counting;
+
+thread_local! {
+    // Only this thread's allocations count, not the test harness's.
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let out = f();
+    (ALLOCATIONS.with(Cell::get) - before, out)
+}
+
+#[test]
+fn one_allocation_per_message() {
+    let keys: Vec<Keypair> = (0..8).map(|_| Keypair::generate()).collect();
+    let recps: Vec<PublicKey> = keys.iter().map(|k| k.public).collect();
+    let plaintext = vec![7; 10_000];
+    // The first use of the system RNG may set things up.
+    encrypt(&plaintext, &recps[..1]).unwrap();
+
+    for n in [1, 2, 8] {
+        let (count, cypher) = allocations(|| encrypt(&plaintext, &recps[..n]).unwrap());
+        assert_eq!(count, 1, "encrypt to {} recipients", n);
+
+        let (count, out) = allocations(|| decrypt(&cypher, &keys[n - 1]).unwrap());
+        assert_eq!(count, 1, "decrypt from {} recipients", n);
+        assert_eq!(out, plaintext);
+    }
+}
@@ -0,0 +1,104 @@
+//! Checks messages round trip between this crate and the js private-box, by
+//! running `test-case-creator/interop.js` under node.
+//!
+//! Needs node, and `npm install` to have been run in `test-case-creator`.
+//! If either is missing, the tests print why and pass.
+#![cfg(feature = "node-interop")]
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use private_box::{decrypt, encrypt, Keypair};
+use serde_json::json;
+
+/// The js private-box only tries this many key slots by default.
+const JS_MAX_RECIPIENTS: usize = 7;
+
+/// Runs a request through the node script, returning what it printed, or
+/// `None` if node or the script's packages aren't installed.
+fn node(request: serde_json::Value) -> Option<Vec<u8>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-case-creator");
+    let mut child = match Command::new("node")
+        .arg("interop.js")
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("skipping: node isn't installed");
+            return None;
+        }
+        Err(e) => panic!("couldn't run node: {}", e),
+    };
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(request.to_string().as_bytes())
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    if output.status.code() == Some(2) {
+        eprintln!("skipping: run `npm install` in test-case-creator first");
+        return None;
+    }
+    assert!(output.status.success(), "interop.js failed");
+    Some(base64::decode(&output.stdout).unwrap())
+}
+
+fn plaintexts() -> Vec<Vec<u8>> {
+    vec![
+        b"hello from the other side".to_vec(),
+        (0..=255).cycle().take(5000).collect(),
+    ]
+}
+
+#[test]
+fn js_messages_decrypt() {
+    let keys: Vec<Keypair> = (0..JS_MAX_RECIPIENTS)
+        .map(|_| Keypair::generate())
+        .collect();
+
+    for n in [1, JS_MAX_RECIPIENTS] {
+        let recps: Vec<String> = keys[..n].iter().map(|k| k.public.as_base64()).collect();
+        for msg in plaintexts() {
+            let request = json!({"encrypt": {"msg": base64::encode(&msg), "recps": recps}});
+            let cypher = match node(request) {
+                Some(cypher) => cypher,
+                None => return,
+            };
+            for key in &keys[..n] {
+                assert_eq!(decrypt(&cypher, key).unwrap(), msg);
+            }
+            assert!(decrypt(&cypher, &Keypair::generate()).is_none());
+        }
+    }
+}
+
+#[test]
+fn messages_decrypt_in_js() {
+    let keys: Vec<Keypair> = (0..JS_MAX_RECIPIENTS)
+        .map(|_| Keypair::generate())
+        .collect();
+    let recps: Vec<_> = keys.iter().map(|k| k.public).collect();
+
+    for n in [1, JS_MAX_RECIPIENTS] {
+        for msg in plaintexts() {
+            let cypher = base64::encode(encrypt(&msg, &recps[..n]).unwrap());
+            for key in &keys[..n] {
+                let request = json!({"decrypt": {"cypher": cypher, "secret": key.as_base64()}});
+                match node(request) {
+                    Some(plaintext) => assert_eq!(plaintext, msg),
+                    None => return,
+                }
+            }
+            let outsider = Keypair::generate().as_base64();
+            let request = json!({"decrypt": {"cypher": cypher, "secret": outsider}});
+            assert_eq!(node(request).unwrap(), b"");
+        }
+    }
+}